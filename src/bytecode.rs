@@ -0,0 +1,331 @@
+use std::convert::TryFrom;
+use std::collections::HashMap;
+use std::fmt::Write;
+
+use super::lexer::{canonical, Span, Token};
+use super::typecheck::Type;
+use super::{RuntimeError, TreeNode, VarType};
+
+//Instructions of the stack machine. Variables live in numbered slots, every
+//type decision has been made by the compiler from the checked tree, so the VM
+//never looks at a name or a Token.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Op{
+    PushInt(i64),
+    PushReal(f64),
+    //push the value of a slot, an error if it was never assigned
+    Load(usize),
+    //pop into a slot
+    Store(usize),
+    //INTEGER on top of the stack to REAL
+    ToReal,
+    //the bool is whether overflow is an error ({$Q+}) or wraps
+    AddInt(bool),
+    SubInt(bool),
+    MulInt(bool),
+    DivInt(bool),
+    ModInt,
+    NegInt(bool),
+    AddReal,
+    SubReal,
+    MulReal,
+    DivReal,
+    NegReal,
+    //error unless the divisor on top of the stack is non-zero
+    CheckDivisor,
+    //error if the MOD divisor on top of the stack is negative
+    CheckModulus,
+    //error unless the INTEGER on top of the stack lies in low..high, the slot names the variable
+    CheckRange(i64, i64, usize),
+}
+
+//compiled program
+pub struct Chunk{
+    pub code: Vec<Op>,
+    //where a runtime error of each instruction is reported
+    spans: Vec<Span>,
+    //declared spelling of the variable in each slot
+    pub names: Vec<String>,
+}
+
+struct Compiler{
+    chunk: Chunk,
+    //canonical name to slot
+    slots: HashMap<String, usize>,
+}
+
+//Compile a type checked program.
+pub fn compile(root: &TreeNode) -> Result<Chunk, RuntimeError>{
+    let mut c = Compiler{chunk: Chunk{code: Vec::new(), spans: Vec::new(), names: Vec::new()}, slots: HashMap::new()};
+    for node in root.sub_nodes[1].sub_nodes.iter(){
+        match &node.node{
+            Token::ASTNode(s) if s == "VARDEC" => c.var_decl(node),
+            Token::ASTNode(s) if s == "COMP" => c.compound(node)?,
+            _ => panic!("error in fn compile, wrong AST node: {:?}", node.node),
+        }
+    }
+    Ok(c.chunk)
+}
+
+impl Compiler{
+    fn emit(&mut self, op: Op, span: Span){
+        self.chunk.code.push(op);
+        self.chunk.spans.push(span);
+    }
+
+    fn slot(&self, var: &TreeNode) -> usize{
+        match &var.node{
+            Token::ID(s) => self.slots[&canonical(s)],
+            _ => panic!("error in fn slot, wrong variable: {:?}", var.node),
+        }
+    }
+
+    fn var_decl(&mut self, root: &TreeNode){
+        if let Token::ID(s) = &root.sub_nodes[0].node{
+            self.slots.insert(canonical(s), self.chunk.names.len());
+            self.chunk.names.push(s.clone());
+        }
+    }
+
+    fn compound(&mut self, root: &TreeNode) -> Result<(), RuntimeError>{
+        for node in root.sub_nodes.iter(){
+            match &node.node{
+                Token::ASSIGN => self.assign(node)?,
+                Token::ASTNode(s) if s == "COMP" => self.compound(node)?,
+                Token::ASTNode(s) if s == "Empty" => {},
+                _ => panic!("error in fn compound, wrong statement: {:?}", node.node),
+            }
+        }
+        Ok(())
+    }
+
+    fn assign(&mut self, root: &TreeNode) -> Result<(), RuntimeError>{
+        let target = &root.sub_nodes[0];
+        let value = &root.sub_nodes[1];
+        let slot = self.slot(target);
+        self.expr(value)?;
+        match (target.ty.expect("error in fn assign, tree has not been type checked"), base_type(value)){
+            (Type::Real, Type::Integer) => self.emit(Op::ToReal, value.span),
+            (Type::Subrange(low, high), _) if root.checks.range => self.emit(Op::CheckRange(low, high, slot), value.span),
+            _ => {},
+        }
+        self.emit(Op::Store(slot), root.span);
+        Ok(())
+    }
+
+    fn expr(&mut self, root: &TreeNode) -> Result<(), RuntimeError>{
+        match &root.node{
+            Token::INTEGER_CONST(n) => match i64::try_from(*n){
+                Ok(n) => self.emit(Op::PushInt(n), root.span),
+                Err(_) => return Err(RuntimeError::new(root.span, format!("integer constant {} is too large", n))),
+            },
+            Token::REAL_CONST(n) => self.emit(Op::PushReal(*n), root.span),
+            Token::ID(_) => self.emit(Op::Load(self.slot(root)), root.span),
+            Token::KEYWORD(s) if s == "DIV" || s == "MOD" => {
+                self.expr(&root.sub_nodes[0])?;
+                self.expr(&root.sub_nodes[1])?;
+                self.emit(Op::CheckDivisor, root.sub_nodes[1].span);
+                if s == "DIV"{
+                    self.emit(Op::DivInt(root.checks.overflow), root.span);
+                }else{
+                    self.emit(Op::CheckModulus, root.sub_nodes[1].span);
+                    self.emit(Op::ModInt, root.span);
+                }
+            },
+            Token::OP1(c) | Token::OP2(c) => {
+                let real = base_type(root) == Type::Real;
+                for operand in root.sub_nodes.iter(){
+                    self.expr(operand)?;
+                    if real && base_type(operand) == Type::Integer{
+                        self.emit(Op::ToReal, operand.span);
+                    }
+                }
+                let overflow = root.checks.overflow;
+                let op = match (c, real){
+                    ('+', false) => Op::AddInt(overflow),
+                    ('-', false) => Op::SubInt(overflow),
+                    ('*', false) => Op::MulInt(overflow),
+                    ('+', true) => Op::AddReal,
+                    ('-', true) => Op::SubReal,
+                    ('*', true) => Op::MulReal,
+                    ('/', true) => {
+                        self.emit(Op::CheckDivisor, root.sub_nodes[1].span);
+                        Op::DivReal
+                    },
+                    _ => panic!("wrong operation: {} on {}", c, base_type(root)),
+                };
+                self.emit(op, root.span);
+            },
+            Token::UNARY(c) => {
+                self.expr(&root.sub_nodes[0])?;
+                match (c, base_type(root)){
+                    ('+', _) => {},
+                    ('-', Type::Real) => self.emit(Op::NegReal, root.span),
+                    ('-', _) => self.emit(Op::NegInt(root.checks.overflow), root.span),
+                    _ => panic!("can not recgnize UNARY: {:?}", c),
+                }
+            },
+            _ => panic!("error in fn expr, wrong node: {:?}", root.node),
+        }
+        Ok(())
+    }
+}
+
+fn base_type(node: &TreeNode) -> Type{
+    node.ty.expect("error in fn base_type, tree has not been type checked").base()
+}
+
+impl Chunk{
+    //one instruction per line: index, opcode, operands
+    pub fn disassemble(&self) -> String{
+        let mut out = String::new();
+        for (i, slot) in self.names.iter().enumerate(){
+            let _ = writeln!(out, ".var {} {}", i, slot);
+        }
+        for (i, op) in self.code.iter().enumerate(){
+            let text = match op{
+                Op::PushInt(n) => format!("push_int    {}", n),
+                Op::PushReal(n) => format!("push_real   {:?}", n),
+                Op::Load(s) => format!("load        {} ({})", s, self.names[*s]),
+                Op::Store(s) => format!("store       {} ({})", s, self.names[*s]),
+                Op::ToReal => "to_real".to_string(),
+                Op::AddInt(checked) => int_op("add_int", *checked),
+                Op::SubInt(checked) => int_op("sub_int", *checked),
+                Op::MulInt(checked) => int_op("mul_int", *checked),
+                Op::DivInt(checked) => int_op("div_int", *checked),
+                Op::ModInt => "mod_int".to_string(),
+                Op::NegInt(checked) => int_op("neg_int", *checked),
+                Op::AddReal => "add_real".to_string(),
+                Op::SubReal => "sub_real".to_string(),
+                Op::MulReal => "mul_real".to_string(),
+                Op::DivReal => "div_real".to_string(),
+                Op::NegReal => "neg_real".to_string(),
+                Op::CheckDivisor => "check_divisor".to_string(),
+                Op::CheckModulus => "check_modulus".to_string(),
+                Op::CheckRange(low, high, s) => format!("check_range {}..{} ({})", low, high, self.names[*s]),
+            };
+            let _ = writeln!(out, "{:04}  {}", i, text);
+        }
+        out
+    }
+}
+
+fn int_op(name: &str, checked: bool) -> String{
+    if checked {name.to_string()} else {format!("{}     wrapping", name)}
+}
+
+//Runs a `Chunk`. After `run` the slots hold the final values of the variables.
+pub struct Vm{
+    pub slots: Vec<Option<VarType>>,
+    stack: Vec<VarType>,
+}
+
+impl Default for Vm{
+    fn default() -> Self{
+        Vm::new()
+    }
+}
+
+impl Vm{
+    pub fn new() -> Self{
+        Vm{slots: Vec::new(), stack: Vec::new()}
+    }
+
+    pub fn run(&mut self, chunk: &Chunk) -> Result<(), RuntimeError>{
+        self.slots = vec![None; chunk.names.len()];
+        self.stack.clear();
+
+        for (op, span) in chunk.code.iter().zip(chunk.spans.iter()){
+            let error = |message: String| Err(RuntimeError::new(*span, message));
+            match *op{
+                Op::PushInt(n) => self.stack.push(VarType::Integer(n)),
+                Op::PushReal(n) => self.stack.push(VarType::Real(n)),
+                Op::Load(s) => match &self.slots[s]{
+                    Some(v) => self.stack.push(v.clone()),
+                    None => return error(format!("variable `{}` has not been initialized", chunk.names[s])),
+                },
+                Op::Store(s) => self.slots[s] = Some(self.pop()),
+                Op::ToReal => {
+                    let n = self.pop().as_f64();
+                    self.stack.push(VarType::Real(n));
+                },
+                Op::AddInt(checked) => self.int_op(checked, i64::checked_add, i64::wrapping_add, span)?,
+                Op::SubInt(checked) => self.int_op(checked, i64::checked_sub, i64::wrapping_sub, span)?,
+                Op::MulInt(checked) => self.int_op(checked, i64::checked_mul, i64::wrapping_mul, span)?,
+                Op::DivInt(checked) => self.int_op(checked, i64::checked_div, i64::wrapping_div, span)?,
+                Op::ModInt => {
+                    let b = self.pop().as_i64();
+                    let a = self.pop().as_i64();
+                    self.stack.push(VarType::Integer(a.rem_euclid(b)));
+                },
+                Op::NegInt(checked) => {
+                    let n = self.pop().as_i64();
+                    match n.checked_neg(){
+                        Some(n) => self.stack.push(VarType::Integer(n)),
+                        None if checked => return error("integer overflow".to_string()),
+                        None => self.stack.push(VarType::Integer(n.wrapping_neg())),
+                    }
+                },
+                Op::AddReal => self.real_op(|a, b| a + b, span)?,
+                Op::SubReal => self.real_op(|a, b| a - b, span)?,
+                Op::MulReal => self.real_op(|a, b| a * b, span)?,
+                Op::DivReal => self.real_op(|a, b| a / b, span)?,
+                Op::NegReal => {
+                    let n = self.pop().as_f64();
+                    self.stack.push(VarType::Real(-n));
+                },
+                Op::CheckDivisor => {
+                    let zero = match self.stack.last(){
+                        Some(VarType::Integer(n)) => *n == 0,
+                        Some(VarType::Real(n)) => *n == 0.0,
+                        None => panic!("error in fn run, empty stack"),
+                    };
+                    if zero{
+                        return error("division by zero".to_string());
+                    }
+                },
+                Op::CheckModulus => {
+                    if let Some(VarType::Integer(n)) = self.stack.last(){
+                        if *n < 0{
+                            return error(format!("MOD by negative value {}", n));
+                        }
+                    }
+                },
+                Op::CheckRange(low, high, s) => {
+                    if let Some(VarType::Integer(n)) = self.stack.last(){
+                        if *n < low || *n > high{
+                            return error(format!("value {} out of range {}..{} of variable `{}`", n, low, high, chunk.names[s]));
+                        }
+                    }
+                },
+            }
+        }
+        Ok(())
+    }
+
+    fn pop(&mut self) -> VarType{
+        self.stack.pop().expect("error in fn pop, empty stack")
+    }
+
+    fn int_op(&mut self, checked: bool, op: fn(i64, i64) -> Option<i64>, wrapping: fn(i64, i64) -> i64, span: &Span) -> Result<(), RuntimeError>{
+        let b = self.pop().as_i64();
+        let a = self.pop().as_i64();
+        match op(a, b){
+            Some(n) => self.stack.push(VarType::Integer(n)),
+            None if checked => return Err(RuntimeError::new(*span, "integer overflow".to_string())),
+            None => self.stack.push(VarType::Integer(wrapping(a, b))),
+        }
+        Ok(())
+    }
+
+    fn real_op(&mut self, op: fn(f64, f64) -> f64, span: &Span) -> Result<(), RuntimeError>{
+        let b = self.pop().as_f64();
+        let a = self.pop().as_f64();
+        let n = op(a, b);
+        if !n.is_finite(){
+            return Err(RuntimeError::new(*span, format!("invalid floating point result {}", n)));
+        }
+        self.stack.push(VarType::Real(n));
+        Ok(())
+    }
+}
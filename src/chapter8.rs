@@ -185,6 +185,10 @@ impl fmt::Debug for Box<TreeNode>{
                         s.push_back(node.right.as_ref().unwrap());
                         this_level_num += 2;
                     },
+                    Token::UNARY(_) => {
+                        s.push_back(node.left.as_ref().unwrap());
+                        this_level_num += 1;
+                    },
                     _ => panic!("illegal node when debug: {:?}", node.OP),
                 }
             }
@@ -194,7 +198,34 @@ impl fmt::Debug for Box<TreeNode>{
     }
 }
 
+//indented tree, one node per line
+impl fmt::Display for TreeNode{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result{
+        self.write_tree(f, "", "")
+    }
+}
+
 impl TreeNode{
+    //`prefix` goes before this node's line, `indent` before the lines of its children
+    fn write_tree(&self, f: &mut fmt::Formatter, prefix: &str, indent: &str) -> fmt::Result{
+        match self.OP{
+            Token::INTEGER(n) => writeln!(f, "{}Num {}", prefix, n)?,
+            Token::UNARY(c) => writeln!(f, "{}UnaryOp {}", prefix, c)?,
+            Token::OP1(c) | Token::OP2(c) => writeln!(f, "{}BinOp {}", prefix, c)?,
+            _ => writeln!(f, "{}{:?}", prefix, self.OP)?,
+        }
+
+        let children: Vec<&Box<TreeNode>> = self.left.iter().chain(self.right.iter()).collect();
+        for (i, node) in children.iter().enumerate(){
+            if i + 1 == children.len(){
+                node.write_tree(f, &format!("{}└── ", indent), &format!("{}    ", indent))?;
+            }else{
+                node.write_tree(f, &format!("{}├── ", indent), &format!("{}│   ", indent))?;
+            }
+        }
+        Ok(())
+    }
+
     pub fn post_t(&self){
         let mut ret = String::new();
         match &self.OP{
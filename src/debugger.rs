@@ -0,0 +1,295 @@
+use std::collections::BTreeSet;
+use std::convert::Infallible;
+use std::io::{BufRead, Write};
+
+use super::lexer::{canonical, Lexer, Token};
+use super::preprocess::SourceMap;
+use super::typecheck::TypeChecker;
+use super::visitor::Visitor;
+use super::{Interpreter, RuntimeError, StatementHook, TreeNode, VarType, Visit};
+
+const HELP: &str = "\
+step, s              stop at the next statement, also inside BEGIN ... END
+next, n              stop at the next statement, running a BEGIN ... END as one
+finish, out          run to the end of the current BEGIN ... END
+continue, c          run to the next breakpoint or watchpoint
+break [FILE:]LINE    stop before the statements of a line
+delete [FILE:]LINE   remove a breakpoint
+watch VAR            stop when the value of a variable changes
+display EXPR         print an expression at every stop
+undisplay N          stop printing display N
+print EXPR, p EXPR   print the value of an expression
+locals               print every variable
+backtrace, bt        print the call stack
+quit, q              stop the program";
+
+enum Mode{
+    Step,
+    //stop at a statement at most this deep
+    Next(usize),
+    //stop at a statement less deep
+    Finish(usize),
+    Continue,
+}
+
+struct Watchpoint{
+    //declared spelling
+    name: String,
+    key: String,
+    last: Option<VarType>,
+}
+
+//A source level debugger driven by `Visit::visit_with_hook`. It stops before
+//the first statement, reads commands from `input` and writes to `output`.
+//There are no procedures or functions, so the call stack is the program
+//alone and stepping in and out is over BEGIN ... END blocks.
+pub struct Debugger<'a, R, W>{
+    map: &'a SourceMap,
+    program: String,
+    //knows the declared variables, for print and display
+    checker: TypeChecker,
+    input: R,
+    output: W,
+    prompt: bool,
+    mode: Mode,
+    //file and line of every statement
+    lines: BTreeSet<(String, usize)>,
+    breakpoints: BTreeSet<(String, usize)>,
+    watchpoints: Vec<Watchpoint>,
+    //number and expression
+    displays: Vec<(usize, String)>,
+    next_display: usize,
+    //line of the previous statement, a breakpoint stops once per line
+    last_line: Option<(String, usize)>,
+    //input ended, the program runs to its end
+    detached: bool,
+    quit: bool,
+}
+
+impl<'a, R: BufRead, W: Write> Debugger<'a, R, W>{
+    //`root` is the type checked program that will be run
+    pub fn new(root: &TreeNode, map: &'a SourceMap, input: R, output: W) -> Self{
+        let program = match &root.sub_nodes[0].node{
+            Token::ID(s) => s.clone(),
+            _ => String::new(),
+        };
+        let mut decls: Vec<TreeNode> = root.sub_nodes[1].sub_nodes.iter()
+            .filter(|n| n.node == Token::ASTNode("VARDEC".to_string())).cloned().collect();
+        let mut checker = TypeChecker::new();
+        let _ = checker.check_declarations(&mut decls);
+        let mut statements = StatementLines{map, lines: BTreeSet::new()};
+        let Ok(()) = statements.visit_node(root);
+        let lines = statements.lines;
+
+        Debugger{
+            map, program, checker, input, output, prompt: false, mode: Mode::Step, lines,
+            breakpoints: BTreeSet::new(), watchpoints: Vec::new(), displays: Vec::new(), next_display: 1,
+            last_line: None, detached: false, quit: false,
+        }
+    }
+
+    //write `(debug) ` before reading each command
+    pub fn with_prompt(mut self, on: bool) -> Self{
+        self.prompt = on;
+        self
+    }
+
+    //whether the program was stopped with `quit`, its error is then not a
+    //runtime error of the program
+    pub fn quit_requested(&self) -> bool{
+        self.quit
+    }
+
+    //report the watchpoints the last statement triggered
+    pub fn finished(&mut self, visit: &Visit){
+        for change in self.watch_changes(visit){
+            let _ = writeln!(self.output, "{}", change);
+        }
+    }
+
+    fn watch_changes(&mut self, visit: &Visit) -> Vec<String>{
+        let mut changes = Vec::new();
+        for w in self.watchpoints.iter_mut(){
+            let now = visit.var_table[&w.key].clone();
+            if format!("{:?}", now) != format!("{:?}", w.last){
+                changes.push(format!("watchpoint {}: {} -> {}", w.name, value_text(&w.last), value_text(&now)));
+                w.last = now;
+            }
+        }
+        changes
+    }
+
+    fn commands(&mut self, visit: &mut Visit, statement: &TreeNode, depth: usize) -> Result<(), RuntimeError>{
+        loop{
+            if self.prompt{
+                let _ = write!(self.output, "(debug) ");
+                let _ = self.output.flush();
+            }
+            let mut line = String::new();
+            if !matches!(self.input.read_line(&mut line), Ok(n) if n > 0){
+                self.detached = true;
+                return Ok(());
+            }
+            let line = line.trim();
+            let (command, arg) = line.split_once(char::is_whitespace).map_or((line, ""), |(c, a)| (c, a.trim()));
+
+            let reply = match command{
+                "" => continue,
+                "s" | "step" => {self.mode = Mode::Step; return Ok(());},
+                "n" | "next" => {self.mode = Mode::Next(depth); return Ok(());},
+                "finish" | "out" => {self.mode = Mode::Finish(depth); return Ok(());},
+                "c" | "continue" => {self.mode = Mode::Continue; return Ok(());},
+                "q" | "quit" => {
+                    self.quit = true;
+                    return Err(RuntimeError::new(statement.span, "stopped by the debugger".to_string()));
+                },
+                "b" | "break" => match self.line_arg(arg){
+                    Ok(at) => {
+                        let reply = format!("breakpoint {}:{}", at.0, at.1);
+                        self.breakpoints.insert(at);
+                        reply
+                    },
+                    Err(e) => e,
+                },
+                "delete" => match self.line_arg(arg){
+                    Ok(at) if self.breakpoints.remove(&at) => format!("deleted breakpoint {}:{}", at.0, at.1),
+                    Ok(at) => format!("no breakpoint at {}:{}", at.0, at.1),
+                    Err(e) => e,
+                },
+                "watch" => match visit.var_names.iter().find(|n| canonical(n) == canonical(arg)){
+                    Some(name) => {
+                        let key = canonical(name);
+                        self.watchpoints.push(Watchpoint{name: name.clone(), last: visit.var_table[&key].clone(), key});
+                        format!("watchpoint on {}", name)
+                    },
+                    None => format!("no variable `{}`", arg),
+                },
+                "display" if !arg.is_empty() => {
+                    self.displays.push((self.next_display, arg.to_string()));
+                    self.next_display += 1;
+                    let value = self.evaluate(visit, arg);
+                    format!("{}: {} = {}", self.next_display - 1, arg, value)
+                },
+                "undisplay" => match arg.parse::<usize>().ok().and_then(|n| self.displays.iter().position(|d| d.0 == n)){
+                    Some(i) => {self.displays.remove(i); format!("removed display {}", arg)},
+                    None => format!("no display `{}`", arg),
+                },
+                "p" | "print" if !arg.is_empty() => self.evaluate(visit, arg),
+                "locals" => visit.var_names.iter().map(|name| format!("{}: {}", name, value_text(&visit.var_table[&canonical(name)])))
+                    .collect::<Vec<_>>().join("\n"),
+                "bt" | "backtrace" => format!("#0 program {} at {}", self.program, self.map.describe(statement.span)),
+                "help" => HELP.to_string(),
+                c => format!("unknown command `{}`, try help", c),
+            };
+            if !reply.is_empty(){
+                let _ = writeln!(self.output, "{}", reply);
+            }
+        }
+    }
+
+    //`LINE` in the main file or `FILE:LINE`, which must hold a statement
+    fn line_arg(&self, arg: &str) -> Result<(String, usize), String>{
+        let (file, line) = match arg.rsplit_once(':'){
+            Some((file, line)) => (file.to_string(), line),
+            None => (self.map.locate(0).0.to_string(), arg),
+        };
+        let line = line.parse::<usize>().map_err(|_| format!("expected [FILE:]LINE, found `{}`", arg))?;
+        if !self.lines.contains(&(file.clone(), line)){
+            return Err(format!("no statement on {}:{}", file, line));
+        }
+        Ok((file, line))
+    }
+
+    //value of an expression over the current variables, or the error
+    fn evaluate(&mut self, visit: &mut Visit, text: &str) -> String{
+        let map = SourceMap::single("<expr>", text);
+        let mut node = match Interpreter::new(Lexer::new(text)).parse_expression(){
+            Ok(node) => node,
+            Err(e) => return e.render(&map),
+        };
+        if let Err(errors) = self.checker.check_expression(&mut node){
+            return errors.iter().map(|e| e.render(&map)).collect::<Vec<_>>().join("\n");
+        }
+        match visit.evaluate(&node){
+            Ok(value) => format!("{:?}", value),
+            Err(e) => e.render(&map),
+        }
+    }
+}
+
+impl<R: BufRead, W: Write> StatementHook for Debugger<'_, R, W>{
+    fn before_statement(&mut self, visit: &mut Visit, statement: &TreeNode, depth: usize) -> Result<(), RuntimeError>{
+        if self.detached{
+            return Ok(());
+        }
+        let mut reasons = self.watch_changes(visit);
+        let (file, line, _) = self.map.locate(statement.span.start);
+        let here = (file.to_string(), line);
+        if self.breakpoints.contains(&here) && self.last_line.as_ref() != Some(&here){
+            reasons.push(format!("breakpoint {}:{}", file, line));
+        }
+        self.last_line = Some(here);
+
+        let stop = !reasons.is_empty() || match self.mode{
+            Mode::Step => true,
+            Mode::Next(d) => depth <= d,
+            Mode::Finish(d) => depth < d,
+            Mode::Continue => false,
+        };
+        if !stop{
+            return Ok(());
+        }
+        for reason in reasons.iter(){
+            let _ = writeln!(self.output, "{}", reason);
+        }
+        let _ = writeln!(self.output, "{}: {}", self.map.describe(statement.span), self.map.line(statement.span.start).trim());
+        for (n, expr) in self.displays.clone().iter(){
+            let value = self.evaluate(visit, expr);
+            let _ = writeln!(self.output, "{}: {} = {}", n, expr, value);
+        }
+        self.commands(visit, statement, depth)
+    }
+}
+
+//file and line of every statement the hook is called for
+struct StatementLines<'a>{
+    map: &'a SourceMap,
+    lines: BTreeSet<(String, usize)>,
+}
+
+impl StatementLines<'_>{
+    fn add(&mut self, node: &TreeNode){
+        let (file, line, _) = self.map.locate(node.span.start);
+        self.lines.insert((file.to_string(), line));
+    }
+}
+
+impl Visitor for StatementLines<'_>{
+    type Output = ();
+    type Error = Infallible;
+
+    //the program body is not a statement of its own
+    fn visit_block(&mut self, node: &TreeNode) -> Result<(), Infallible>{
+        match node.sub_nodes.last(){
+            Some(body) => self.walk_compound(body),
+            None => Ok(()),
+        }
+    }
+
+    fn visit_compound(&mut self, node: &TreeNode) -> Result<(), Infallible>{
+        self.add(node);
+        self.walk_compound(node)
+    }
+
+    fn visit_assign(&mut self, node: &TreeNode) -> Result<(), Infallible>{
+        self.add(node);
+        Ok(())
+    }
+}
+
+fn value_text(value: &Option<VarType>) -> String{
+    match value{
+        Some(value) => format!("{:?}", value),
+        None => "<not initialized>".to_string(),
+    }
+}
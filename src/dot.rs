@@ -0,0 +1,53 @@
+use std::fmt::Write;
+
+use super::TreeNode;
+
+//what `to_dot_with` adds to the label of each node
+#[derive(Clone, Copy, Default)]
+pub struct DotOptions{
+    //static type from the type checker, if the tree has been checked
+    pub types: bool,
+    pub spans: bool,
+}
+
+impl TreeNode{
+    //Graphviz digraph with one labeled node per AST node and an edge to each child
+    pub fn to_dot(&self) -> String{
+        self.to_dot_with(DotOptions::default())
+    }
+
+    pub fn to_dot_with(&self, options: DotOptions) -> String{
+        let mut out = String::from("digraph AST {\n    node [shape=box, fontname=\"monospace\"];\n");
+        let mut next_id = 0;
+        write_node(&mut out, self, options, &mut next_id);
+        out.push_str("}\n");
+        out
+    }
+}
+
+//returns the id of the node written
+fn write_node(out: &mut String, root: &TreeNode, options: DotOptions, next_id: &mut usize) -> usize{
+    let id = *next_id;
+    *next_id += 1;
+
+    let mut label = root.label();
+    if options.types{
+        if let Some(ty) = root.ty{
+            let _ = write!(label, "\n{}", ty);
+        }
+    }
+    if options.spans{
+        let _ = write!(label, "\n[{}..{})", root.span.start, root.span.end);
+    }
+    let _ = writeln!(out, "    n{} [label=\"{}\"];", id, escape(&label));
+
+    for node in root.sub_nodes.iter(){
+        let child = write_node(out, node, options, next_id);
+        let _ = writeln!(out, "    n{} -> n{};", id, child);
+    }
+    id
+}
+
+pub(crate) fn escape(label: &str) -> String{
+    label.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
@@ -0,0 +1,335 @@
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::fmt::Write;
+
+use super::lexer::{canonical, Span, Token};
+use super::preprocess::SourceMap;
+use super::typecheck::Type;
+use super::visitor::Visitor;
+use super::{RuntimeError, TreeNode};
+
+//Runtime routines the error paths jump to. They report like `Visit` on fd 2
+//and exit with status 2; the stack is realigned first since they are entered
+//from the middle of an expression.
+const RUNTIME: &str = r#"
+# rdi location, rsi message
+rt_fail:
+    and $-16, %rsp
+    mov %rsi, %rcx
+    mov %rdi, %rdx
+    lea .Lfmt_fail(%rip), %rsi
+    mov $2, %edi
+    xor %eax, %eax
+    call dprintf@PLT
+    mov $2, %edi
+    call exit@PLT
+
+# rdi location, rsi divisor
+rt_fail_mod:
+    and $-16, %rsp
+    mov %rsi, %rcx
+    mov %rdi, %rdx
+    lea .Lfmt_mod(%rip), %rsi
+    mov $2, %edi
+    xor %eax, %eax
+    call dprintf@PLT
+    mov $2, %edi
+    call exit@PLT
+
+# rdi location, rsi value, rdx low, rcx high, r8 variable name
+rt_fail_range:
+    and $-16, %rsp
+    sub $8, %rsp
+    push %r8
+    mov %rcx, %r9
+    mov %rdx, %r8
+    mov %rsi, %rcx
+    mov %rdi, %rdx
+    lea .Lfmt_range(%rip), %rsi
+    mov $2, %edi
+    xor %eax, %eax
+    call dprintf@PLT
+    mov $2, %edi
+    call exit@PLT
+
+    .section .rodata
+.Lfmt_fail:
+    .asciz "%s: runtime error: %s\n"
+.Lfmt_mod:
+    .asciz "%s: runtime error: MOD by negative value %ld\n"
+.Lfmt_range:
+    .asciz "%s: runtime error: value %ld out of range %ld..%ld of variable `%s`\n"
+.Lfmt_int:
+    .asciz "%s: INTEGER(%ld)\n"
+.Lfmt_unset:
+    .asciz "%s: <not initialized>\n"
+.Linteger_overflow:
+    .asciz "integer overflow"
+"#;
+
+//Translates a type checked INTEGER program to x86-64 GNU assembler for Linux,
+//linked against libc. The value of an expression is computed in %rax, a
+//pending left operand is kept on the stack.
+struct AsmEmitter<'a>{
+    map: &'a SourceMap,
+    //canonical name to symbol
+    vars: HashMap<String, String>,
+    //declared spelling in declaration order
+    names: Vec<String>,
+    text: String,
+    //out of line error paths, after the code of main
+    errors: String,
+    //string literal to label
+    strings: HashMap<String, String>,
+    labels: usize,
+}
+
+pub fn emit_asm(root: &TreeNode, map: &SourceMap) -> Result<String, RuntimeError>{
+    let mut e = AsmEmitter{map, vars: HashMap::new(), names: Vec::new(), text: String::new(), errors: String::new(), strings: HashMap::new(), labels: 0};
+    let name = match &root.sub_nodes[0].node{
+        Token::ID(s) => s.clone(),
+        _ => String::new(),
+    };
+    e.visit_program(root)?;
+    for name in e.names.clone().iter(){
+        let symbol = e.vars[&canonical(name)].clone();
+        let label = e.string(name);
+        let (unset, next) = (e.label(), e.label());
+        e.line(&format!("cmpb $0, {}_set(%rip)", symbol));
+        e.line(&format!("je {}", unset));
+        e.line(&format!("mov {}(%rip), %rdx", symbol));
+        e.line(&format!("lea {}(%rip), %rsi", label));
+        e.line("lea .Lfmt_int(%rip), %rdi");
+        e.line("xor %eax, %eax");
+        e.line("call printf@PLT");
+        e.line(&format!("jmp {}", next));
+        e.text += &format!("{}:\n", unset);
+        e.line(&format!("lea {}(%rip), %rsi", label));
+        e.line("lea .Lfmt_unset(%rip), %rdi");
+        e.line("xor %eax, %eax");
+        e.line("call printf@PLT");
+        e.text += &format!("{}:\n", next);
+    }
+
+    let mut out = format!("# program {}\n    .text\n    .globl main\nmain:\n    push %rbp\n    mov %rsp, %rbp\n", name);
+    out += &e.text;
+    out += "    xor %eax, %eax\n    pop %rbp\n    ret\n";
+    out += &e.errors;
+    out += RUNTIME;
+    let mut strings: Vec<(&String, &String)> = e.strings.iter().collect();
+    strings.sort_by(|a, b| a.1.cmp(b.1));
+    for (s, label) in strings{
+        let _ = writeln!(out, "{}:\n    .asciz {}", label, gas_string(s));
+    }
+    out += "\n    .bss\n";
+    for name in e.names.iter(){
+        let symbol = &e.vars[&canonical(name)];
+        let _ = writeln!(out, "    .align 8\n{}:\n    .zero 8\n{}_set:\n    .zero 1", symbol, symbol);
+    }
+    out += "\n    .section .note.GNU-stack,\"\",@progbits\n";
+    Ok(out)
+}
+
+impl AsmEmitter<'_>{
+    fn line(&mut self, text: &str){
+        let _ = writeln!(self.text, "    {}", text);
+    }
+
+    fn label(&mut self) -> String{
+        self.labels += 1;
+        format!(".L{}", self.labels)
+    }
+
+    //label of a string in .rodata, each string is stored once
+    fn string(&mut self, s: &str) -> String{
+        let count = self.strings.len();
+        self.strings.entry(s.to_string()).or_insert_with(|| format!(".Lstr{:04}", count)).clone()
+    }
+
+    //jump target of an error path that loads `setup` and jumps to `routine`
+    fn error(&mut self, span: Span, setup: &[String], routine: &str) -> String{
+        let label = self.label();
+        let location = self.map.describe(span);
+        let location = self.string(&location);
+        let _ = writeln!(self.errors, "{}:\n    lea {}(%rip), %rdi", label, location);
+        for line in setup.iter(){
+            let _ = writeln!(self.errors, "    {}", line);
+        }
+        let _ = writeln!(self.errors, "    jmp {}", routine);
+        label
+    }
+
+    fn fail(&mut self, span: Span, message: &str) -> String{
+        let message = self.string(message);
+        self.error(span, &[format!("lea {}(%rip), %rsi", message)], "rt_fail")
+    }
+
+    fn overflow(&mut self, span: Span) -> String{
+        self.error(span, &["lea .Linteger_overflow(%rip), %rsi".to_string()], "rt_fail")
+    }
+
+    //leaves the value of `root` in %rax
+    fn expr(&mut self, root: &TreeNode) -> Result<(), RuntimeError>{
+        if root.ty.map(Type::base) == Some(Type::Real){
+            return Err(RuntimeError::new(root.span, "emit-asm does not support REAL arithmetic".to_string()));
+        }
+        self.visit_node(root)
+    }
+
+    //%rax op %rcx into %rax
+    fn binary_op(&mut self, root: &TreeNode) -> Result<(), RuntimeError>{
+        let checked = root.checks.overflow;
+        let divisor = root.sub_nodes[1].span;
+        let instruction = match &root.node{
+            Token::OP1('+') => "add %rcx, %rax",
+            Token::OP1('-') => "sub %rcx, %rax",
+            Token::OP2('*') => "imul %rcx, %rax",
+            Token::KEYWORD(s) if s == "DIV" => {
+                let zero = self.fail(divisor, "division by zero");
+                let (divide, done) = (self.label(), self.label());
+                self.line("test %rcx, %rcx");
+                self.line(&format!("jz {}", zero));
+                //x DIV -1 is -x, which also catches the one overflow of idiv
+                self.line("cmp $-1, %rcx");
+                self.line(&format!("jne {}", divide));
+                self.line("neg %rax");
+                if checked{
+                    let error = self.overflow(root.span);
+                    self.line(&format!("jo {}", error));
+                }
+                self.line(&format!("jmp {}", done));
+                self.text += &format!("{}:\n", divide);
+                self.line("cqo");
+                self.line("idiv %rcx");
+                self.text += &format!("{}:\n", done);
+                return Ok(());
+            },
+            Token::KEYWORD(s) if s == "MOD" => {
+                let zero = self.fail(divisor, "division by zero");
+                let negative = self.error(divisor, &["mov %rcx, %rsi".to_string()], "rt_fail_mod");
+                let done = self.label();
+                self.line("test %rcx, %rcx");
+                self.line(&format!("jz {}", zero));
+                self.line(&format!("js {}", negative));
+                self.line("cqo");
+                self.line("idiv %rcx");
+                self.line("mov %rdx, %rax");
+                self.line("test %rax, %rax");
+                self.line(&format!("jns {}", done));
+                self.line("add %rcx, %rax");
+                self.text += &format!("{}:\n", done);
+                return Ok(());
+            },
+            _ => panic!("error in fn binary_op, wrong operator: {:?}", root.node),
+        };
+        self.line(instruction);
+        if checked{
+            let error = self.overflow(root.span);
+            self.line(&format!("jo {}", error));
+        }
+        Ok(())
+    }
+}
+
+//Statements and expressions are emitted in visiting order, an expression
+//leaves its value in %rax. The first unsupported construct stops the walk.
+impl Visitor for AsmEmitter<'_>{
+    type Output = ();
+    type Error = RuntimeError;
+
+    fn visit_var_decl(&mut self, root: &TreeNode) -> Result<(), RuntimeError>{
+        let var = &root.sub_nodes[0];
+        if let (Token::ID(s), Some(ty)) = (&var.node, var.ty){
+            if ty == Type::Real{
+                return Err(RuntimeError::new(var.span, format!("emit-asm only supports INTEGER variables, `{}` is REAL", s)));
+            }
+            self.vars.insert(canonical(s), format!("v_{}", canonical(s)));
+            self.names.push(s.clone());
+        }
+        Ok(())
+    }
+
+    fn visit_assign(&mut self, root: &TreeNode) -> Result<(), RuntimeError>{
+        let name = match &root.sub_nodes[0].node{
+            Token::ID(s) => s.clone(),
+            _ => panic!("error in fn visit_assign, wrong var_name: {:?}", root.sub_nodes[0].node),
+        };
+        let value_node = &root.sub_nodes[1];
+        self.expr(value_node)?;
+        if let (Some(Type::Subrange(low, high)), true) = (root.sub_nodes[0].ty, root.checks.range){
+            let name_label = self.string(&name);
+            let error = self.error(value_node.span, &[
+                "mov %rax, %rsi".to_string(),
+                format!("movabs ${}, %rdx", low),
+                format!("movabs ${}, %rcx", high),
+                format!("lea {}(%rip), %r8", name_label),
+            ], "rt_fail_range");
+            self.line(&format!("movabs ${}, %rdx", low));
+            self.line("cmp %rdx, %rax");
+            self.line(&format!("jl {}", error));
+            self.line(&format!("movabs ${}, %rdx", high));
+            self.line("cmp %rdx, %rax");
+            self.line(&format!("jg {}", error));
+        }
+        let symbol = self.vars[&canonical(&name)].clone();
+        self.line(&format!("mov %rax, {}(%rip)", symbol));
+        self.line(&format!("movb $1, {}_set(%rip)", symbol));
+        Ok(())
+    }
+
+    fn visit_num(&mut self, root: &TreeNode) -> Result<(), RuntimeError>{
+        match &root.node{
+            Token::INTEGER_CONST(n) => match i64::try_from(*n){
+                Ok(n) => self.line(&format!("movabs ${}, %rax", n)),
+                Err(_) => return Err(RuntimeError::new(root.span, format!("integer constant {} is too large", n))),
+            },
+            _ => panic!("error in fn visit_num, wrong node: {:?}", root.node),
+        }
+        Ok(())
+    }
+
+    fn visit_var(&mut self, root: &TreeNode) -> Result<(), RuntimeError>{
+        if let Token::ID(s) = &root.node{
+            let symbol = self.vars[&canonical(s)].clone();
+            let error = self.fail(root.span, &format!("variable `{}` has not been initialized", s));
+            self.line(&format!("cmpb $0, {}_set(%rip)", symbol));
+            self.line(&format!("je {}", error));
+            self.line(&format!("mov {}(%rip), %rax", symbol));
+        }
+        Ok(())
+    }
+
+    fn visit_unary_op(&mut self, root: &TreeNode) -> Result<(), RuntimeError>{
+        self.expr(&root.sub_nodes[0])?;
+        if matches!(root.node, Token::UNARY('-')){
+            self.line("neg %rax");
+            if root.checks.overflow{
+                let error = self.overflow(root.span);
+                self.line(&format!("jo {}", error));
+            }
+        }
+        Ok(())
+    }
+
+    fn visit_binary_op(&mut self, root: &TreeNode) -> Result<(), RuntimeError>{
+        self.expr(&root.sub_nodes[0])?;
+        self.line("push %rax");
+        self.expr(&root.sub_nodes[1])?;
+        self.line("mov %rax, %rcx");
+        self.line("pop %rax");
+        self.binary_op(root)
+    }
+}
+
+fn gas_string(s: &str) -> String{
+    let mut out = String::from("\"");
+    for &b in s.as_bytes(){
+        match b{
+            b'"' | b'\\' => {out.push('\\'); out.push(b as char);},
+            0x20..=0x7e => out.push(b as char),
+            _ => {let _ = write!(out, "\\{:03o}", b);},
+        }
+    }
+    out.push('"');
+    out
+}
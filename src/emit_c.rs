@@ -0,0 +1,324 @@
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::fmt::Write;
+
+use super::lexer::{canonical, Span, Token};
+use super::preprocess::SourceMap;
+use super::typecheck::Type;
+use super::{RuntimeError, TreeNode};
+
+//Runtime support copied into every translation unit. The checks mirror `Visit`:
+//same messages, same positions, exit status 2 and nothing on stdout. Overflow
+//is detected without compiler builtins, wrapping goes through uint64_t. The
+//functions are not static, so the ones a program does not use are not warned about.
+const PRELUDE: &str = r#"#include <inttypes.h>
+#include <math.h>
+#include <stdint.h>
+#include <stdio.h>
+#include <stdlib.h>
+
+void fail(const char *location, const char *message){
+    fprintf(stderr, "%s: runtime error: %s\n", location, message);
+    exit(2);
+}
+
+void fail_range(const char *location, int64_t n, int64_t low, int64_t high, const char *name){
+    fprintf(stderr, "%s: runtime error: value %" PRId64 " out of range %" PRId64 "..%" PRId64 " of variable `%s`\n", location, n, low, high, name);
+    exit(2);
+}
+
+/* the shortest digits that read back as x, written without an exponent */
+void print_real(FILE *out, double x){
+    char buf[40], digits[20];
+    int p, n = 0, i, exp;
+    if (isnan(x)){ fputs("NaN", out); return; }
+    if (signbit(x)){ fputc('-', out); x = -x; }
+    if (isinf(x)){ fputs("inf", out); return; }
+    if (x == 0.0){ fputc('0', out); return; }
+    for (p = 0; p < 16; p++){
+        snprintf(buf, sizeof buf, "%.*e", p, x);
+        if (strtod(buf, NULL) == x) break;
+    }
+    snprintf(buf, sizeof buf, "%.*e", p, x);
+    for (i = 0; buf[i] != 'e'; i++){
+        if (buf[i] != '.') digits[n++] = buf[i];
+    }
+    exp = atoi(buf + i + 1);
+    if (exp >= 0){
+        for (i = 0; i <= exp; i++) fputc(i < n ? digits[i] : '0', out);
+        if (n > exp + 1){
+            fputc('.', out);
+            for (i = exp + 1; i < n; i++) fputc(digits[i], out);
+        }
+    }else{
+        fputs("0.", out);
+        for (i = 0; i < -exp - 1; i++) fputc('0', out);
+        for (i = 0; i < n; i++) fputc(digits[i], out);
+    }
+}
+
+double real_result(double x, const char *location){
+    if (!isfinite(x)){
+        fprintf(stderr, "%s: runtime error: invalid floating point result ", location);
+        print_real(stderr, x);
+        fputc('\n', stderr);
+        exit(2);
+    }
+    return x;
+}
+
+int64_t wrap(uint64_t n){
+    return n <= INT64_MAX ? (int64_t)n : -(int64_t)(~n) - 1;
+}
+
+int64_t add_int(int64_t a, int64_t b, int checked, const char *location){
+    if (checked && ((b > 0 && a > INT64_MAX - b) || (b < 0 && a < INT64_MIN - b))) fail(location, "integer overflow");
+    return wrap((uint64_t)a + (uint64_t)b);
+}
+
+int64_t sub_int(int64_t a, int64_t b, int checked, const char *location){
+    if (checked && ((b < 0 && a > INT64_MAX + b) || (b > 0 && a < INT64_MIN + b))) fail(location, "integer overflow");
+    return wrap((uint64_t)a - (uint64_t)b);
+}
+
+int64_t mul_int(int64_t a, int64_t b, int checked, const char *location){
+    int overflow;
+    if (a == 0 || b == 0) overflow = 0;
+    else if (a == -1) overflow = b == INT64_MIN;
+    else if (b == -1) overflow = a == INT64_MIN;
+    else if (a > 0) overflow = b > 0 ? a > INT64_MAX / b : b < INT64_MIN / a;
+    else overflow = b > 0 ? a < INT64_MIN / b : a < INT64_MAX / b;
+    if (checked && overflow) fail(location, "integer overflow");
+    return wrap((uint64_t)a * (uint64_t)b);
+}
+
+int64_t div_int(int64_t a, int64_t b, int checked, const char *divisor, const char *location){
+    if (b == 0) fail(divisor, "division by zero");
+    if (a == INT64_MIN && b == -1){
+        if (checked) fail(location, "integer overflow");
+        return INT64_MIN;
+    }
+    return a / b;
+}
+
+int64_t mod_int(int64_t a, int64_t b, const char *divisor){
+    int64_t r;
+    if (b == 0) fail(divisor, "division by zero");
+    if (b < 0){
+        fprintf(stderr, "%s: runtime error: MOD by negative value %" PRId64 "\n", divisor, b);
+        exit(2);
+    }
+    r = a % b;
+    return r < 0 ? r + b : r;
+}
+
+int64_t neg_int(int64_t a, int checked, const char *location){
+    if (a == INT64_MIN){
+        if (checked) fail(location, "integer overflow");
+        return INT64_MIN;
+    }
+    return -a;
+}
+
+double div_real(double a, double b, const char *divisor, const char *location){
+    if (b == 0.0) fail(divisor, "division by zero");
+    return real_result(a / b, location);
+}
+"#;
+
+struct Var{
+    c_name: String,
+    ty: Type,
+}
+
+//Translates a type checked program to one C99 translation unit whose output
+//matches `interpreter-ast run`.
+struct CEmitter<'a>{
+    map: &'a SourceMap,
+    //canonical name to variable
+    vars: HashMap<String, Var>,
+    //declared spelling in declaration order
+    names: Vec<String>,
+    body: String,
+    temps: usize,
+}
+
+pub fn emit_c(root: &TreeNode, map: &SourceMap) -> Result<String, RuntimeError>{
+    let mut e = CEmitter{map, vars: HashMap::new(), names: Vec::new(), body: String::new(), temps: 0};
+    let name = match &root.sub_nodes[0].node{
+        Token::ID(s) => s.clone(),
+        _ => String::new(),
+    };
+    for node in root.sub_nodes[1].sub_nodes.iter(){
+        match &node.node{
+            Token::ASTNode(s) if s == "VARDEC" => e.var_decl(node),
+            Token::ASTNode(s) if s == "COMP" => e.compound(node)?,
+            _ => panic!("error in fn emit_c, wrong AST node: {:?}", node.node),
+        }
+    }
+
+    let mut out = format!("/* program {} */\n", name);
+    out += PRELUDE;
+    out.push('\n');
+    for name in e.names.iter(){
+        let var = &e.vars[&canonical(name)];
+        let c_type = if var.ty == Type::Real {"double"} else {"int64_t"};
+        let _ = writeln!(out, "static {} {};\nstatic int {}_set;", c_type, var.c_name, var.c_name);
+    }
+    out += "\nint main(void){\n";
+    out += &e.body;
+    for name in e.names.iter(){
+        let var = &e.vars[&canonical(name)];
+        let _ = writeln!(out, "    if (!{}_set) printf(\"%s: <not initialized>\\n\", \"{}\");", var.c_name, name);
+        if var.ty == Type::Real{
+            let _ = writeln!(out, "    else{{ printf(\"%s: REAL(\", \"{}\"); print_real(stdout, {}); printf(\")\\n\"); }}", name, var.c_name);
+        }else{
+            let _ = writeln!(out, "    else printf(\"%s: INTEGER(%\" PRId64 \")\\n\", \"{}\", {});", name, var.c_name);
+        }
+    }
+    out += "    return 0;\n}\n";
+    Ok(out)
+}
+
+impl CEmitter<'_>{
+    fn line(&mut self, text: &str){
+        let _ = writeln!(self.body, "    {}", text);
+    }
+
+    fn temp(&mut self) -> String{
+        self.temps += 1;
+        format!("t{}", self.temps)
+    }
+
+    //C string literal with the `file:line:col` of a span
+    fn location(&self, span: Span) -> String{
+        c_string(&self.map.describe(span))
+    }
+
+    fn var_decl(&mut self, root: &TreeNode){
+        if let (Token::ID(s), Some(ty)) = (&root.sub_nodes[0].node, root.sub_nodes[0].ty){
+            //a prefix keeps Pascal names clear of C keywords and the prelude
+            let c_name = format!("v_{}", canonical(s));
+            self.vars.insert(canonical(s), Var{c_name, ty});
+            self.names.push(s.clone());
+        }
+    }
+
+    fn compound(&mut self, root: &TreeNode) -> Result<(), RuntimeError>{
+        for node in root.sub_nodes.iter(){
+            match &node.node{
+                Token::ASSIGN => self.assign(node)?,
+                Token::ASTNode(s) if s == "COMP" => self.compound(node)?,
+                Token::ASTNode(s) if s == "Empty" => {},
+                _ => panic!("error in fn compound, wrong statement: {:?}", node.node),
+            }
+        }
+        Ok(())
+    }
+
+    fn assign(&mut self, root: &TreeNode) -> Result<(), RuntimeError>{
+        let name = match &root.sub_nodes[0].node{
+            Token::ID(s) => s.clone(),
+            _ => panic!("error in fn assign, wrong var_name: {:?}", root.sub_nodes[0].node),
+        };
+        let value_node = &root.sub_nodes[1];
+        let (value, value_ty) = self.expr(value_node)?;
+        let c_name = self.vars[&canonical(&name)].c_name.clone();
+
+        let value = match (self.vars[&canonical(&name)].ty, value_ty){
+            (Type::Real, Type::Integer) => format!("(double){}", value),
+            (Type::Subrange(low, high), _) if root.checks.range => {
+                let location = self.location(value_node.span);
+                self.line(&format!("if ({v} < {lo} || {v} > {hi}) fail_range({}, {v}, {lo}, {hi}, {});",
+                    location, c_string(&name), v = value, lo = int_literal(low), hi = int_literal(high)));
+                value
+            },
+            _ => value,
+        };
+        self.line(&format!("{} = {};", c_name, value));
+        self.line(&format!("{}_set = 1;", c_name));
+        Ok(())
+    }
+
+    //C expression for the value of `root` and its base type. Operations are
+    //written into temporaries so errors are raised in the order `Visit` raises them.
+    fn expr(&mut self, root: &TreeNode) -> Result<(String, Type), RuntimeError>{
+        let ty = root.ty.expect("error in fn expr, tree has not been type checked").base();
+        let location = self.location(root.span);
+        let checked = if root.checks.overflow {1} else {0};
+
+        let value = match &root.node{
+            Token::INTEGER_CONST(n) => match i64::try_from(*n){
+                Ok(n) => return Ok((int_literal(n), Type::Integer)),
+                Err(_) => return Err(RuntimeError::new(root.span, format!("integer constant {} is too large", n))),
+            },
+            Token::REAL_CONST(n) => return Ok((format!("{:e}", n), Type::Real)),
+            Token::ID(s) => {
+                let c_name = self.vars[&canonical(s)].c_name.clone();
+                let message = c_string(&format!("variable `{}` has not been initialized", s));
+                self.line(&format!("if (!{}_set) fail({}, {});", c_name, location, message));
+                return Ok((c_name, ty));
+            },
+            Token::KEYWORD(s) if s == "DIV" || s == "MOD" => {
+                let (left, _) = self.expr(&root.sub_nodes[0])?;
+                let (right, _) = self.expr(&root.sub_nodes[1])?;
+                let divisor = self.location(root.sub_nodes[1].span);
+                if s == "DIV"{
+                    format!("div_int({}, {}, {}, {}, {})", left, right, checked, divisor, location)
+                }else{
+                    format!("mod_int({}, {}, {})", left, right, divisor)
+                }
+            },
+            Token::OP1(c) | Token::OP2(c) => {
+                let (mut left, left_ty) = self.expr(&root.sub_nodes[0])?;
+                let (mut right, right_ty) = self.expr(&root.sub_nodes[1])?;
+                if ty == Type::Real{
+                    if left_ty == Type::Integer {left = format!("(double){}", left);}
+                    if right_ty == Type::Integer {right = format!("(double){}", right);}
+                }
+                match (c, ty){
+                    ('+', Type::Integer) => format!("add_int({}, {}, {}, {})", left, right, checked, location),
+                    ('-', Type::Integer) => format!("sub_int({}, {}, {}, {})", left, right, checked, location),
+                    ('*', Type::Integer) => format!("mul_int({}, {}, {}, {})", left, right, checked, location),
+                    ('/', _) => format!("div_real({}, {}, {}, {})", left, right, self.location(root.sub_nodes[1].span), location),
+                    (c, _) => format!("real_result({} {} {}, {})", left, c, right, location),
+                }
+            },
+            Token::UNARY(c) => {
+                let (operand, _) = self.expr(&root.sub_nodes[0])?;
+                match (c, ty){
+                    ('+', _) => return Ok((operand, ty)),
+                    ('-', Type::Real) => format!("-{}", operand),
+                    _ => format!("neg_int({}, {}, {})", operand, checked, location),
+                }
+            },
+            _ => panic!("error in fn expr, wrong node: {:?}", root.node),
+        };
+
+        let temp = self.temp();
+        let c_type = if ty == Type::Real {"double"} else {"int64_t"};
+        self.line(&format!("{} {} = {};", c_type, temp, value));
+        Ok((temp, ty))
+    }
+}
+
+fn int_literal(n: i64) -> String{
+    if n == i64::MIN {"INT64_MIN".to_string()} else {format!("INT64_C({})", n)}
+}
+
+fn c_string(s: &str) -> String{
+    let mut out = String::from("\"");
+    for c in s.chars(){
+        match c{
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            //keep `??x` from being read as a trigraph
+            '?' => out.push_str("\\?"),
+            c if (c as u32) < 0x20 => {let _ = write!(out, "\\{:03o}", c as u32);},
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
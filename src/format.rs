@@ -0,0 +1,313 @@
+use super::lexer::{comment_directive, comment_end, comment_start, Lexer, Span, Token, TriviaKind};
+use super::preprocess::is_conditional_or_include;
+use super::{Interpreter, ParseError, TreeNode};
+
+const INDENT: &str = "   ";
+
+struct Comment{
+    span: Span,
+    //the comment follows a token on the same line
+    trailing: bool,
+    //a `{$...}` directive, which must stay between the same tokens
+    directive: bool,
+}
+
+//Prints a parsed program back as canonically laid out Pascal: upper-case
+//keywords, one statement per line, aligned VAR declarations. Comments are
+//taken from the trivia of the source and re-attached by position. A line
+//with a directive inside it is copied as written, since the directive
+//switches checks from that token on.
+struct Formatter{
+    text: Vec<char>,
+    nested_comments: bool,
+    comments: Vec<Comment>,
+    //index of the first comment not printed yet
+    next_comment: usize,
+    out: String,
+}
+
+//Format `text` and check that the result parses to the same AST with the
+//same checks. `nested_comments` must match how the program is otherwise lexed.
+pub fn format_source(text: &str, nested_comments: bool) -> Result<String, ParseError>{
+    check_formattable(text, nested_comments)?;
+    let root = Interpreter::new(Lexer::new(text).nested_comments(nested_comments)).parse()?;
+    let formatted = format_program(text, &root, nested_comments)?;
+
+    let again = Interpreter::new(Lexer::new(&formatted).nested_comments(nested_comments)).parse()?;
+    if !root.same_shape(&again) || !same_checks(&root, &again){
+        return Err(ParseError::new(root.span, "internal error: formatting changed the program".to_string()));
+    }
+    Ok(formatted)
+}
+
+//Conditionals and includes can not be formatted: the raw text mixes the
+//branches, and included files are not formatted with it.
+pub fn check_formattable(text: &str, nested_comments: bool) -> Result<(), ParseError>{
+    for token in Lexer::new(text).nested_comments(nested_comments).preserve_trivia(true){
+        //a lexical error is left for the parser to report
+        let token = match token{
+            Ok(token) => token,
+            Err(_) => break,
+        };
+        for t in token.leading.iter().chain(token.trailing.iter()).filter(|t| t.kind == TriviaKind::Directive){
+            let comment: Vec<char> = text.chars().skip(t.span.start).take(t.span.end - t.span.start).collect();
+            if comment_directive(&comment).is_some_and(|d| is_conditional_or_include(&d)){
+                let shown: String = comment.iter().collect();
+                return Err(ParseError::new(t.span, format!("cannot format files with conditional compilation or includes, found `{}`", shown)));
+            }
+        }
+    }
+    Ok(())
+}
+
+//Format the program `root` that was parsed from `text`.
+pub fn format_program(text: &str, root: &TreeNode, nested_comments: bool) -> Result<String, ParseError>{
+    let mut comments = Vec::new();
+    for token in Lexer::new(text).nested_comments(nested_comments).preserve_trivia(true){
+        let token = token?;
+        let trivia = token.leading.iter().map(|t| (t, false)).chain(token.trailing.iter().map(|t| (t, true)));
+        for (t, trailing) in trivia{
+            if t.kind == TriviaKind::Comment || t.kind == TriviaKind::Directive{
+                comments.push(Comment{span: t.span, trailing, directive: t.kind == TriviaKind::Directive});
+            }
+        }
+    }
+
+    let mut f = Formatter{text: text.chars().collect(), nested_comments, comments, next_comment: 0, out: String::new()};
+    f.program(root);
+    Ok(f.out)
+}
+
+impl Formatter{
+    fn program(&mut self, root: &TreeNode){
+        let name = &root.sub_nodes[0];
+        let header = Span::new(root.span.start, name.span.end);
+        self.line_or_source(0, &format!("PROGRAM {};", self.source(name.span)), self.with_separator(header));
+
+        let block = &root.sub_nodes[1];
+        let (decls, comp) = block.sub_nodes.split_at(block.sub_nodes.len() - 1);
+        if !decls.is_empty(){
+            self.declarations(decls);
+        }
+        self.blank_line_before(comp[0].span.start);
+        self.compound(0, &comp[0], ".");
+        self.flush_comments(0, self.text.len());
+    }
+
+    //VARDEC nodes that came from the same `a, b : T` share the span of their type node
+    fn declarations(&mut self, decls: &[TreeNode]){
+        let mut groups: Vec<(Vec<&TreeNode>, &TreeNode)> = Vec::new();
+        for decl in decls.iter(){
+            match groups.last_mut(){
+                Some((names, ty)) if ty.span == decl.sub_nodes[1].span => names.push(&decl.sub_nodes[0]),
+                _ => groups.push((vec![&decl.sub_nodes[0]], &decl.sub_nodes[1])),
+            }
+        }
+
+        let lines: Vec<(String, String, Span)> = groups.iter().map(|(names, ty)| {
+            let names = names.iter().map(|n| self.source(n.span)).collect::<Vec<_>>().join(", ");
+            let span = Span::new(decls.iter().find(|d| d.sub_nodes[1].span == ty.span).unwrap().span.start, ty.span.end);
+            (names, self.type_spec(ty), span)
+        }).collect();
+        let width = lines.iter().map(|(names, _, _)| names.chars().count()).max().unwrap_or(0);
+
+        self.blank_line_before(decls[0].span.start);
+        self.out += "VAR\n";
+        for (names, ty, span) in lines.iter(){
+            self.blank_line_before(span.start);
+            self.line_or_source(1, &format!("{:width$} : {};", names, ty, width = width), self.with_separator(*span));
+        }
+    }
+
+    fn type_spec(&self, root: &TreeNode) -> String{
+        match &root.node{
+            Token::KEYWORD(s) => s.clone(),
+            _ => format!("{}..{}", self.expr(&root.sub_nodes[0]), self.expr(&root.sub_nodes[1])),
+        }
+    }
+
+    //BEGIN ... END followed by `terminator`
+    fn compound(&mut self, level: usize, root: &TreeNode, terminator: &str){
+        self.line(level, "BEGIN", Span::new(root.span.start, root.span.start + 5));
+        let last = root.sub_nodes.len() - 1;
+        for (i, node) in root.sub_nodes.iter().enumerate(){
+            let separator = if i < last {";"} else {""};
+            self.blank_line_before(node.span.start);
+            match &node.node{
+                Token::ASTNode(s) if s == "COMP" => self.compound(level + 1, node, separator),
+                //an empty statement only leaves its separator behind
+                Token::ASTNode(s) if s == "Empty" => {
+                    if !separator.is_empty(){
+                        self.line(level + 1, separator, node.span);
+                    }
+                },
+                _ => {
+                    let text = format!("{}{}", self.statement(node), separator);
+                    let span = if separator.is_empty() {node.span} else {self.with_separator(node.span)};
+                    self.line_or_source(level + 1, &text, span);
+                },
+            }
+        }
+        let end = Span::new(root.span.end - 3, root.span.end);
+        self.line(level, &format!("END{}", terminator), end);
+    }
+
+    fn statement(&self, root: &TreeNode) -> String{
+        match &root.node{
+            Token::ASSIGN => format!("{} := {}", self.source(root.sub_nodes[0].span), self.expr(&root.sub_nodes[1])),
+            _ => panic!("error in fn statement, wrong statement: {:?}", root.node),
+        }
+    }
+
+    fn expr(&self, root: &TreeNode) -> String{
+        match &root.node{
+            Token::INTEGER_CONST(_) | Token::REAL_CONST(_) | Token::ID(_) => self.source(root.span),
+            Token::UNARY(c) => {
+                let operand = &root.sub_nodes[0];
+                if precedence(operand).is_some(){
+                    format!("{}({})", c, self.expr(operand))
+                }else{
+                    format!("{}{}", c, self.expr(operand))
+                }
+            },
+            _ => {
+                let prec = precedence(root).unwrap_or_else(|| panic!("error in fn expr, wrong node: {:?}", root.node));
+                let op = match &root.node{
+                    Token::OP1(c) | Token::OP2(c) => c.to_string(),
+                    Token::KEYWORD(s) => s.clone(),
+                    _ => unreachable!(),
+                };
+                //operators are left associative, so only the right operand needs
+                //parentheses at equal precedence
+                let left = &root.sub_nodes[0];
+                let right = &root.sub_nodes[1];
+                let left = match precedence(left){
+                    Some(p) if p < prec => format!("({})", self.expr(left)),
+                    _ => self.expr(left),
+                };
+                let right = match precedence(right){
+                    Some(p) if p <= prec => format!("({})", self.expr(right)),
+                    _ => self.expr(right),
+                };
+                format!("{} {} {}", left, op, right)
+            },
+        }
+    }
+
+    //`text` for the source range `span`, or the source itself when a directive
+    //comes after its first token. `text` and `span` include the `;` if any.
+    fn line_or_source(&mut self, level: usize, text: &str, span: Span){
+        let directive_inside = self.comments[self.next_comment..].iter()
+            .take_while(|c| c.span.start < span.end)
+            .any(|c| c.directive && c.span.start > span.start);
+        if !directive_inside{
+            self.line(level, text, span);
+            return;
+        }
+        self.flush_comments(level, span.start);
+        self.out += &INDENT.repeat(level);
+        self.out += &self.source(span);
+        while self.comments.get(self.next_comment).is_some_and(|c| c.span.start < span.end){
+            self.next_comment += 1;
+        }
+        self.end_line(span);
+    }
+
+    //whether `start..end` holds nothing but whitespace and comments
+    fn only_comments(&self, start: usize, end: usize) -> bool{
+        let mut i = start;
+        while i < end{
+            match comment_start(&self.text, i){
+                Some(_) => i = comment_end(&self.text, i, self.nested_comments).unwrap_or(self.text.len()),
+                None if self.text[i].is_whitespace() => i += 1,
+                None => return false,
+            }
+        }
+        true
+    }
+
+    //`span` extended past the `;` that follows it, skipping comments
+    fn with_separator(&self, span: Span) -> Span{
+        let mut i = span.end;
+        while i < self.text.len(){
+            if self.text[i] == ';'{
+                return Span::new(span.start, i + 1);
+            }
+            match comment_start(&self.text, i){
+                Some(_) => i = comment_end(&self.text, i, self.nested_comments).unwrap_or(self.text.len()),
+                None => i += 1,
+            }
+        }
+        span
+    }
+
+    //one output line for the source range `span`, with the comments around it
+    fn line(&mut self, level: usize, text: &str, span: Span){
+        self.flush_comments(level, span.start);
+        self.out += &INDENT.repeat(level);
+        self.out += text;
+        self.end_line(span);
+    }
+
+    fn end_line(&mut self, span: Span){
+        //comments inside the range, or later on the same source line, go after it.
+        //A directive does not move past the tokens that follow the range.
+        let line_end = self.text[span.end..].iter().position(|c| *c == '\n').map_or(self.text.len(), |n| span.end + n);
+        while let Some(c) = self.comments.get(self.next_comment){
+            let trailing = c.trailing && c.span.start < line_end && (!c.directive || self.only_comments(span.end, c.span.start));
+            if c.span.start < span.end || trailing{
+                self.out += " ";
+                self.out += &self.source(c.span);
+                self.next_comment += 1;
+            }else{
+                break;
+            }
+        }
+        self.out.push('\n');
+    }
+
+    //comments before `pos` on lines of their own
+    fn flush_comments(&mut self, level: usize, pos: usize){
+        while let Some(c) = self.comments.get(self.next_comment){
+            if c.span.start >= pos{
+                break;
+            }
+            self.out += &INDENT.repeat(level);
+            self.out += &self.source(c.span);
+            self.out.push('\n');
+            self.next_comment += 1;
+        }
+    }
+
+    //keep a single blank line where the source had one or more
+    fn blank_line_before(&mut self, pos: usize){
+        let before = &self.text[..pos];
+        let line_start = before.iter().rposition(|c| *c == '\n');
+        let blank = match line_start{
+            Some(n) if before[..n].iter().rev().take_while(|c| **c != '\n').all(|c| c.is_whitespace()) => n > 0,
+            _ => false,
+        };
+        if blank && !self.out.is_empty() && !self.out.ends_with("\n\n"){
+            self.out.push('\n');
+        }
+    }
+
+    fn source(&self, span: Span) -> String{
+        self.text[span.start..span.end].iter().collect()
+    }
+}
+
+//same `checks` on every node of two trees of the same shape
+fn same_checks(a: &TreeNode, b: &TreeNode) -> bool{
+    a.checks == b.checks && a.sub_nodes.iter().zip(b.sub_nodes.iter()).all(|(a, b)| same_checks(a, b))
+}
+
+//binding strength of a binary operator node, None for everything else
+fn precedence(root: &TreeNode) -> Option<u8>{
+    match &root.node{
+        Token::OP1(_) if root.sub_nodes.len() == 2 => Some(1),
+        Token::OP2(_) => Some(2),
+        Token::KEYWORD(s) if s == "DIV" || s == "MOD" => Some(2),
+        _ => None,
+    }
+}
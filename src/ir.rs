@@ -0,0 +1,349 @@
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::fmt::{self, Write};
+
+use super::dot::escape;
+use super::lexer::{canonical, Span, Token};
+use super::typecheck::Type;
+use super::{RuntimeError, TreeNode};
+
+//Three-address code lowered from a type checked tree. Every intermediate value
+//gets its own temporary, assigned exactly once, and every check the tree-walker
+//makes is an explicit instruction, so a backend needs neither `sub_nodes` nor
+//the checker's annotations. REAL operations are an error when the result is
+//not finite, as in `Visit`.
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Operand{
+    Temp(usize),
+    Int(i64),
+    Real(f64),
+}
+
+//the bool is whether overflow is an error ({$Q+}) or wraps
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BinOp{
+    AddInt(bool),
+    SubInt(bool),
+    MulInt(bool),
+    DivInt(bool),
+    ModInt,
+    AddReal,
+    SubReal,
+    MulReal,
+    DivReal,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum UnOp{
+    NegInt(bool),
+    NegReal,
+    //INTEGER to REAL
+    ToReal,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Instr{
+    //temp = variable, an error if the variable was never assigned
+    Load(usize, usize),
+    //variable := operand
+    Store(usize, Operand),
+    Binary(usize, BinOp, Operand, Operand),
+    Unary(usize, UnOp, Operand),
+    //error unless the divisor is non-zero
+    CheckDivisor(Operand),
+    //error if the MOD divisor is negative
+    CheckModulus(Operand),
+    //error unless the value lies in low..high, the last field names the variable
+    CheckRange(Operand, i64, i64, usize),
+}
+
+//how control leaves a basic block
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Terminator{
+    Goto(usize),
+    Return,
+}
+
+pub struct Block{
+    pub instrs: Vec<Instr>,
+    //where a runtime error of each instruction is reported
+    pub spans: Vec<Span>,
+    pub terminator: Terminator,
+}
+
+pub struct Var{
+    //declared spelling
+    pub name: String,
+    //declared type, subranges included
+    pub ty: Type,
+}
+
+//A lowered program: the variables in declaration order, the type of every
+//temporary, and the control-flow graph with block 0 as entry. The language has
+//no control flow yet, so lowering produces a single block.
+pub struct Program{
+    pub name: String,
+    pub vars: Vec<Var>,
+    //INTEGER or REAL
+    pub temps: Vec<Type>,
+    pub blocks: Vec<Block>,
+}
+
+struct Lowering{
+    program: Program,
+    //canonical name to variable
+    slots: HashMap<String, usize>,
+    //block being filled
+    current: usize,
+}
+
+//Lower a type checked program.
+pub fn lower(root: &TreeNode) -> Result<Program, RuntimeError>{
+    let name = match &root.sub_nodes[0].node{
+        Token::ID(s) => s.clone(),
+        _ => String::new(),
+    };
+    let entry = Block{instrs: Vec::new(), spans: Vec::new(), terminator: Terminator::Return};
+    let mut l = Lowering{program: Program{name, vars: Vec::new(), temps: Vec::new(), blocks: vec![entry]}, slots: HashMap::new(), current: 0};
+    for node in root.sub_nodes[1].sub_nodes.iter(){
+        match &node.node{
+            Token::ASTNode(s) if s == "VARDEC" => l.var_decl(node),
+            Token::ASTNode(s) if s == "COMP" => l.compound(node)?,
+            _ => panic!("error in fn lower, wrong AST node: {:?}", node.node),
+        }
+    }
+    Ok(l.program)
+}
+
+impl Lowering{
+    fn emit(&mut self, instr: Instr, span: Span){
+        let block = &mut self.program.blocks[self.current];
+        block.instrs.push(instr);
+        block.spans.push(span);
+    }
+
+    fn temp(&mut self, ty: Type) -> usize{
+        self.program.temps.push(ty);
+        self.program.temps.len() - 1
+    }
+
+    fn var(&self, var: &TreeNode) -> usize{
+        match &var.node{
+            Token::ID(s) => self.slots[&canonical(s)],
+            _ => panic!("error in fn var, wrong variable: {:?}", var.node),
+        }
+    }
+
+    fn var_decl(&mut self, root: &TreeNode){
+        if let (Token::ID(s), Some(ty)) = (&root.sub_nodes[0].node, root.sub_nodes[0].ty){
+            self.slots.insert(canonical(s), self.program.vars.len());
+            self.program.vars.push(Var{name: s.clone(), ty});
+        }
+    }
+
+    fn compound(&mut self, root: &TreeNode) -> Result<(), RuntimeError>{
+        for node in root.sub_nodes.iter(){
+            match &node.node{
+                Token::ASSIGN => self.assign(node)?,
+                Token::ASTNode(s) if s == "COMP" => self.compound(node)?,
+                Token::ASTNode(s) if s == "Empty" => {},
+                _ => panic!("error in fn compound, wrong statement: {:?}", node.node),
+            }
+        }
+        Ok(())
+    }
+
+    fn assign(&mut self, root: &TreeNode) -> Result<(), RuntimeError>{
+        let target = &root.sub_nodes[0];
+        let value = &root.sub_nodes[1];
+        let var = self.var(target);
+        let mut operand = self.expr(value)?;
+        match (self.program.vars[var].ty, base_type(value)){
+            (Type::Real, Type::Integer) => operand = self.unary(UnOp::ToReal, operand, Type::Real, value.span),
+            (Type::Subrange(low, high), _) if root.checks.range => self.emit(Instr::CheckRange(operand, low, high, var), value.span),
+            _ => {},
+        }
+        self.emit(Instr::Store(var, operand), root.span);
+        Ok(())
+    }
+
+    fn unary(&mut self, op: UnOp, operand: Operand, ty: Type, span: Span) -> Operand{
+        let dest = self.temp(ty);
+        self.emit(Instr::Unary(dest, op, operand), span);
+        Operand::Temp(dest)
+    }
+
+    fn expr(&mut self, root: &TreeNode) -> Result<Operand, RuntimeError>{
+        let ty = base_type(root);
+        let checked = root.checks.overflow;
+        let op = match &root.node{
+            Token::INTEGER_CONST(n) => return match i64::try_from(*n){
+                Ok(n) => Ok(Operand::Int(n)),
+                Err(_) => Err(RuntimeError::new(root.span, format!("integer constant {} is too large", n))),
+            },
+            Token::REAL_CONST(n) => return Ok(Operand::Real(*n)),
+            Token::ID(_) => {
+                let dest = self.temp(ty);
+                let var = self.var(root);
+                self.emit(Instr::Load(dest, var), root.span);
+                return Ok(Operand::Temp(dest));
+            },
+            Token::UNARY(c) => {
+                let operand = self.expr(&root.sub_nodes[0])?;
+                return Ok(match (c, ty){
+                    ('+', _) => operand,
+                    (_, Type::Real) => self.unary(UnOp::NegReal, operand, ty, root.span),
+                    _ => self.unary(UnOp::NegInt(checked), operand, ty, root.span),
+                });
+            },
+            Token::KEYWORD(s) if s == "DIV" => BinOp::DivInt(checked),
+            Token::KEYWORD(s) if s == "MOD" => BinOp::ModInt,
+            Token::OP1(c) | Token::OP2(c) => match (c, ty){
+                ('+', Type::Integer) => BinOp::AddInt(checked),
+                ('-', Type::Integer) => BinOp::SubInt(checked),
+                ('*', Type::Integer) => BinOp::MulInt(checked),
+                ('+', _) => BinOp::AddReal,
+                ('-', _) => BinOp::SubReal,
+                ('*', _) => BinOp::MulReal,
+                _ => BinOp::DivReal,
+            },
+            _ => panic!("error in fn expr, wrong node: {:?}", root.node),
+        };
+
+        let mut operands = Vec::new();
+        for node in root.sub_nodes.iter(){
+            let mut operand = self.expr(node)?;
+            if ty == Type::Real && base_type(node) == Type::Integer{
+                operand = self.unary(UnOp::ToReal, operand, Type::Real, node.span);
+            }
+            operands.push(operand);
+        }
+        let divisor = root.sub_nodes[1].span;
+        match op{
+            BinOp::DivInt(_) | BinOp::DivReal => self.emit(Instr::CheckDivisor(operands[1]), divisor),
+            BinOp::ModInt => {
+                self.emit(Instr::CheckDivisor(operands[1]), divisor);
+                self.emit(Instr::CheckModulus(operands[1]), divisor);
+            },
+            _ => {},
+        }
+        let dest = self.temp(ty);
+        self.emit(Instr::Binary(dest, op, operands[0], operands[1]), root.span);
+        Ok(Operand::Temp(dest))
+    }
+}
+
+fn base_type(node: &TreeNode) -> Type{
+    node.ty.expect("error in fn base_type, tree has not been type checked").base()
+}
+
+impl Block{
+    pub fn successors(&self) -> Vec<usize>{
+        match self.terminator{
+            Terminator::Goto(target) => vec![target],
+            Terminator::Return => Vec::new(),
+        }
+    }
+}
+
+impl Program{
+    //for each block, the blocks that jump to it
+    pub fn predecessors(&self) -> Vec<Vec<usize>>{
+        let mut preds = vec![Vec::new(); self.blocks.len()];
+        for (i, block) in self.blocks.iter().enumerate(){
+            for target in block.successors(){
+                preds[target].push(i);
+            }
+        }
+        preds
+    }
+
+    fn instr_text(&self, instr: &Instr) -> String{
+        let var = |i: usize| &self.vars[i].name;
+        let wrapping = |checked: bool| if checked {""} else {" wrapping"};
+        match *instr{
+            Instr::Load(dest, v) => format!("t{} = {}", dest, var(v)),
+            Instr::Store(v, value) => format!("{} := {}", var(v), value),
+            Instr::Binary(dest, op, left, right) => {
+                let (symbol, checked) = match op{
+                    BinOp::AddInt(c) => ("+", c),
+                    BinOp::SubInt(c) => ("-", c),
+                    BinOp::MulInt(c) => ("*", c),
+                    BinOp::DivInt(c) => ("div", c),
+                    BinOp::ModInt => ("mod", true),
+                    BinOp::AddReal => ("+", true),
+                    BinOp::SubReal => ("-", true),
+                    BinOp::MulReal => ("*", true),
+                    BinOp::DivReal => ("/", true),
+                };
+                format!("t{} = {} {} {}{}", dest, left, symbol, right, wrapping(checked))
+            },
+            Instr::Unary(dest, UnOp::NegInt(checked), operand) => format!("t{} = -{}{}", dest, operand, wrapping(checked)),
+            Instr::Unary(dest, UnOp::NegReal, operand) => format!("t{} = -{}", dest, operand),
+            Instr::Unary(dest, UnOp::ToReal, operand) => format!("t{} = real {}", dest, operand),
+            Instr::CheckDivisor(divisor) => format!("check divisor {}", divisor),
+            Instr::CheckModulus(divisor) => format!("check modulus {}", divisor),
+            Instr::CheckRange(value, low, high, v) => format!("check {} in {}..{} for {}", value, low, high, var(v)),
+        }
+    }
+
+    fn block_text(&self, i: usize) -> Vec<String>{
+        let block = &self.blocks[i];
+        let mut lines: Vec<String> = block.instrs.iter().map(|instr| self.instr_text(instr)).collect();
+        lines.push(match block.terminator{
+            Terminator::Goto(target) => format!("goto bb{}", target),
+            Terminator::Return => "return".to_string(),
+        });
+        lines
+    }
+
+    //Graphviz digraph of the control-flow graph, one node per basic block
+    pub fn to_dot(&self) -> String{
+        let mut out = String::from("digraph CFG {\n    node [shape=box, fontname=\"monospace\"];\n");
+        for i in 0..self.blocks.len(){
+            let mut label = format!("bb{}:\\l", i);
+            for line in self.block_text(i){
+                let _ = write!(label, "    {}\\l", escape(&line));
+            }
+            let _ = writeln!(out, "    bb{} [label=\"{}\"];", i, label);
+        }
+        for (i, block) in self.blocks.iter().enumerate(){
+            for target in block.successors(){
+                let _ = writeln!(out, "    bb{} -> bb{};", i, target);
+            }
+        }
+        out.push_str("}\n");
+        out
+    }
+}
+
+impl fmt::Display for Operand{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result{
+        match self{
+            Operand::Temp(t) => write!(f, "t{}", t),
+            Operand::Int(n) => write!(f, "{}", n),
+            Operand::Real(n) => write!(f, "{:?}", n),
+        }
+    }
+}
+
+//the variables, the temporaries with their types, then the blocks
+impl fmt::Display for Program{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result{
+        writeln!(f, "program {}", self.name)?;
+        for var in self.vars.iter(){
+            writeln!(f, "var {}: {}", var.name, var.ty)?;
+        }
+        for (i, ty) in self.temps.iter().enumerate(){
+            writeln!(f, "temp t{}: {}", i, ty)?;
+        }
+        for i in 0..self.blocks.len(){
+            writeln!(f, "\nbb{}:", i)?;
+            for line in self.block_text(i){
+                writeln!(f, "    {}", line)?;
+            }
+        }
+        Ok(())
+    }
+}
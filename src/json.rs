@@ -0,0 +1,430 @@
+use std::fmt;
+use std::fmt::Write;
+
+use super::lexer::{Checks, Span, Token};
+use super::TreeNode;
+
+//A node is written as
+//  {"kind": "ASSIGN", "span": [12, 18], "checks": {...}, "children": [...]}
+//with a "value" for operators, keywords, literals and identifiers:
+//  {"kind": "OP1", "value": "+", ...}      {"kind": "INTEGER_CONST", "value": 42, ...}
+//  {"kind": "KEYWORD", "value": "DIV", ...} {"kind": "ID", "value": "Total", ...}
+//AST nodes (PROGRAM, BLOCK, VARDEC, SUBRANGE, COMP, Empty) use their name as kind.
+//"checks" may be left out when loading, all checks are then on.
+
+pub struct JsonError{
+    //char offset into the JSON text
+    pub offset: usize,
+    pub message: String,
+}
+
+impl fmt::Display for JsonError{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result{
+        write!(f, "json error at offset {}: {}", self.offset, self.message)
+    }
+}
+
+enum Value{
+    Null,
+    Bool(bool),
+    //kept as written so that INTEGER_CONST values do not go through f64
+    Number(String),
+    Str(String),
+    Array(Vec<(usize, Value)>),
+    Object(Vec<(String, usize, Value)>),
+}
+
+impl TreeNode{
+    pub fn to_json(&self) -> String{
+        let mut out = String::new();
+        write_node(&mut out, self, 0);
+        out.push('\n');
+        out
+    }
+
+    //load a tree written by `to_json`, or by another tool using the same layout
+    pub fn from_json(text: &str) -> Result<TreeNode, JsonError>{
+        let mut parser = Parser{text: text.chars().collect(), idx: 0};
+        parser.skip_whitespace();
+        let start = parser.idx;
+        let value = parser.value()?;
+        parser.skip_whitespace();
+        if parser.idx < parser.text.len(){
+            return Err(parser.error("trailing characters after the tree"));
+        }
+        let root = read_node(start, &value)?;
+        if root.node != Token::ASTNode("PROGRAM".to_string()){
+            return Err(JsonError{offset: start, message: "the root node must be a PROGRAM".to_string()});
+        }
+        Ok(root)
+    }
+}
+
+fn write_node(out: &mut String, root: &TreeNode, level: usize){
+    let indent = "  ".repeat(level + 1);
+    let (kind, value) = match &root.node{
+        Token::ASTNode(s) => (s.clone(), None),
+        Token::ASSIGN => ("ASSIGN".to_string(), None),
+        Token::OP1(c) => ("OP1".to_string(), Some(quote(&c.to_string()))),
+        Token::OP2(c) => ("OP2".to_string(), Some(quote(&c.to_string()))),
+        Token::UNARY(c) => ("UNARY".to_string(), Some(quote(&c.to_string()))),
+        Token::KEYWORD(s) => ("KEYWORD".to_string(), Some(quote(s))),
+        Token::INTEGER_CONST(n) => ("INTEGER_CONST".to_string(), Some(n.to_string())),
+        //`{:?}` is the shortest text that reads back as the same f64
+        Token::REAL_CONST(n) => ("REAL_CONST".to_string(), Some(format!("{:?}", n))),
+        Token::ID(s) => ("ID".to_string(), Some(quote(s))),
+        _ => panic!("error in fn write_node, wrong node: {:?}", root.node),
+    };
+
+    let _ = write!(out, "{{\n{}\"kind\": {}", indent, quote(&kind));
+    if let Some(value) = value{
+        let _ = write!(out, ",\n{}\"value\": {}", indent, value);
+    }
+    let _ = write!(out, ",\n{}\"span\": [{}, {}]", indent, root.span.start, root.span.end);
+    let c = root.checks;
+    let _ = write!(out, ",\n{}\"checks\": {{\"range\": {}, \"overflow\": {}, \"io\": {}}}", indent, c.range, c.overflow, c.io);
+    let _ = write!(out, ",\n{}\"children\": [", indent);
+    for (i, node) in root.sub_nodes.iter().enumerate(){
+        out.push_str(if i == 0 {"\n"} else {",\n"});
+        out.push_str(&"  ".repeat(level + 2));
+        write_node(out, node, level + 2);
+    }
+    if !root.sub_nodes.is_empty(){
+        let _ = write!(out, "\n{}", indent);
+    }
+    let _ = write!(out, "]\n{}}}", "  ".repeat(level));
+}
+
+fn quote(s: &str) -> String{
+    let mut out = String::from("\"");
+    for c in s.chars(){
+        match c{
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => {let _ = write!(out, "\\u{:04x}", c as u32);},
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+fn read_node(offset: usize, value: &Value) -> Result<TreeNode, JsonError>{
+    let fields = match value{
+        Value::Object(fields) => fields,
+        _ => return Err(JsonError{offset, message: "expected a node object".to_string()}),
+    };
+    let field = |name: &str| fields.iter().find(|(k, _, _)| k == name).map(|(_, o, v)| (*o, v));
+    let error = |offset: usize, message: String| Err(JsonError{offset, message});
+
+    let kind = match field("kind"){
+        Some((_, Value::Str(s))) => s.as_str(),
+        Some((o, _)) => return error(o, "`kind` must be a string".to_string()),
+        None => return error(offset, "node has no `kind`".to_string()),
+    };
+    let (value_offset, value) = field("value").unwrap_or((offset, &Value::Null));
+    let single_char = |value: &Value| match value{
+        Value::Str(s) if s.chars().count() == 1 => s.chars().next(),
+        _ => None,
+    };
+
+    //the token of the node and its allowed number of children
+    let (node, arity): (Token, &[usize]) = match kind{
+        "PROGRAM" | "VARDEC" | "SUBRANGE" => (Token::ASTNode(kind.to_string()), &[2]),
+        "BLOCK" | "COMP" => (Token::ASTNode(kind.to_string()), &[]),
+        "Empty" => (Token::ASTNode(kind.to_string()), &[0]),
+        "ASSIGN" => (Token::ASSIGN, &[2]),
+        "OP1" => match single_char(value){
+            Some(c) if c == '+' || c == '-' => (Token::OP1(c), &[2]),
+            _ => return error(value_offset, "OP1 value must be \"+\" or \"-\"".to_string()),
+        },
+        "OP2" => match single_char(value){
+            Some(c) if c == '*' || c == '/' => (Token::OP2(c), &[2]),
+            _ => return error(value_offset, "OP2 value must be \"*\" or \"/\"".to_string()),
+        },
+        "UNARY" => match single_char(value){
+            Some(c) if c == '+' || c == '-' => (Token::UNARY(c), &[1]),
+            _ => return error(value_offset, "UNARY value must be \"+\" or \"-\"".to_string()),
+        },
+        "KEYWORD" => match value{
+            Value::Str(s) if s == "DIV" || s == "MOD" => (Token::KEYWORD(s.clone()), &[2]),
+            Value::Str(s) if s == "INTEGER" || s == "REAL" => (Token::KEYWORD(s.clone()), &[0]),
+            _ => return error(value_offset, "KEYWORD value must be DIV, MOD, INTEGER or REAL".to_string()),
+        },
+        "INTEGER_CONST" => match value{
+            Value::Number(s) => match s.parse::<u64>(){
+                Ok(n) if n <= i64::MAX as u64 => (Token::INTEGER_CONST(n), &[0]),
+                _ => return error(value_offset, format!("`{}` is not a valid INTEGER constant", s)),
+            },
+            _ => return error(value_offset, "INTEGER_CONST value must be a number".to_string()),
+        },
+        "REAL_CONST" => match value{
+            Value::Number(s) => match s.parse::<f64>(){
+                Ok(n) if n.is_finite() && n >= 0.0 => (Token::REAL_CONST(n), &[0]),
+                _ => return error(value_offset, format!("`{}` is not a valid REAL constant", s)),
+            },
+            _ => return error(value_offset, "REAL_CONST value must be a number".to_string()),
+        },
+        "ID" => match value{
+            Value::Str(s) if is_identifier(s) => (Token::ID(s.clone()), &[0]),
+            _ => return error(value_offset, "ID value must be an identifier".to_string()),
+        },
+        _ => return error(offset, format!("unknown node kind `{}`", kind)),
+    };
+
+    let span = match field("span"){
+        Some((o, Value::Array(items))) => match items.as_slice(){
+            [(_, Value::Number(start)), (_, Value::Number(end))] => match (start.parse::<usize>(), end.parse::<usize>()){
+                (Ok(start), Ok(end)) if start <= end => Span::new(start, end),
+                _ => return error(o, "`span` must be two increasing offsets".to_string()),
+            },
+            _ => return error(o, "`span` must be [start, end]".to_string()),
+        },
+        Some((o, _)) => return error(o, "`span` must be [start, end]".to_string()),
+        None => Span::default(),
+    };
+
+    let mut checks = Checks::default();
+    match field("checks"){
+        Some((_, Value::Object(flags))) => {
+            for (name, o, flag) in flags.iter(){
+                let flag = match flag{
+                    Value::Bool(b) => *b,
+                    _ => return error(*o, format!("check `{}` must be true or false", name)),
+                };
+                match name.as_str(){
+                    "range" => checks.range = flag,
+                    "overflow" => checks.overflow = flag,
+                    "io" => checks.io = flag,
+                    _ => return error(*o, format!("unknown check `{}`", name)),
+                }
+            }
+        },
+        Some((o, _)) => return error(o, "`checks` must be an object".to_string()),
+        None => {},
+    }
+
+    let (children_offset, children) = match field("children"){
+        Some((o, Value::Array(items))) => {
+            let nodes = items.iter().map(|(o, v)| read_node(*o, v).map(|node| (*o, node))).collect::<Result<Vec<_>, _>>()?;
+            (o, nodes)
+        },
+        Some((o, _)) => return error(o, "`children` must be an array".to_string()),
+        None => (offset, Vec::new()),
+    };
+    let count_ok = if arity.is_empty() {!children.is_empty()} else {arity.contains(&children.len())};
+    if !count_ok{
+        return error(children_offset, format!("{} node can not have {} children", kind, children.len()));
+    }
+    for (n, (o, child)) in children.iter().enumerate(){
+        let role = Role::of(kind, n);
+        if !role.fits(child){
+            return error(*o, format!("{} child {} must be {}, found `{}`", kind, n, role.describe(), child.label()));
+        }
+    }
+    let sub_nodes = children.into_iter().map(|(_, node)| node).collect();
+
+    Ok(TreeNode::new(node, sub_nodes, span).with_checks(checks))
+}
+
+//what a child of a node must be, so that a loaded tree can be checked and run
+#[derive(Clone, Copy)]
+enum Role{
+    Name,
+    Block,
+    Declaration,
+    TypeSpec,
+    Statement,
+    Expression,
+}
+
+impl Role{
+    //role of child `n` of a node of kind `kind`
+    fn of(kind: &str, n: usize) -> Role{
+        match (kind, n){
+            ("PROGRAM", 0) | ("VARDEC", 0) | ("ASSIGN", 0) => Role::Name,
+            ("PROGRAM", _) => Role::Block,
+            ("BLOCK", _) => Role::Declaration,
+            ("VARDEC", _) => Role::TypeSpec,
+            ("COMP", _) => Role::Statement,
+            _ => Role::Expression,
+        }
+    }
+
+    fn fits(self, node: &TreeNode) -> bool{
+        let ast = |s: &str| node.node == Token::ASTNode(s.to_string());
+        let keyword = |words: &[&str]| matches!(&node.node, Token::KEYWORD(s) if words.contains(&s.as_str()));
+        match self{
+            Role::Name => matches!(node.node, Token::ID(_)),
+            Role::Block => ast("BLOCK"),
+            Role::Declaration => ast("VARDEC") || ast("COMP"),
+            Role::TypeSpec => ast("SUBRANGE") || keyword(&["INTEGER", "REAL"]),
+            Role::Statement => node.node == Token::ASSIGN || ast("COMP") || ast("Empty"),
+            Role::Expression => keyword(&["DIV", "MOD"]) || matches!(node.node,
+                Token::ID(_) | Token::INTEGER_CONST(_) | Token::REAL_CONST(_) | Token::OP1(_) | Token::OP2(_) | Token::UNARY(_)),
+        }
+    }
+
+    fn describe(self) -> &'static str{
+        match self{
+            Role::Name => "an ID",
+            Role::Block => "a BLOCK",
+            Role::Declaration => "a VARDEC or COMP",
+            Role::TypeSpec => "INTEGER, REAL or a SUBRANGE",
+            Role::Statement => "an ASSIGN, COMP or Empty",
+            Role::Expression => "an expression",
+        }
+    }
+}
+
+//the names the lexer produces, [A-Za-z_][A-Za-z0-9_]*
+fn is_identifier(s: &str) -> bool{
+    let mut chars = s.chars();
+    matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_') && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+struct Parser{
+    text: Vec<char>,
+    idx: usize,
+}
+
+impl Parser{
+    fn error(&self, message: &str) -> JsonError{
+        JsonError{offset: self.idx, message: message.to_string()}
+    }
+
+    fn skip_whitespace(&mut self){
+        while self.idx < self.text.len() && self.text[self.idx].is_whitespace(){
+            self.idx += 1;
+        }
+    }
+
+    fn expect(&mut self, c: char) -> Result<(), JsonError>{
+        self.skip_whitespace();
+        if self.text.get(self.idx) == Some(&c){
+            self.idx += 1;
+            Ok(())
+        }else{
+            Err(self.error(&format!("expected `{}`", c)))
+        }
+    }
+
+    fn value(&mut self) -> Result<Value, JsonError>{
+        self.skip_whitespace();
+        match self.text.get(self.idx){
+            Some('{') => self.object(),
+            Some('[') => self.array(),
+            Some('"') => Ok(Value::Str(self.string()?)),
+            Some(c) if *c == '-' || c.is_ascii_digit() => Ok(self.number()),
+            Some(_) => {
+                for (word, value) in [("true", Value::Bool(true)), ("false", Value::Bool(false)), ("null", Value::Null)]{
+                    if self.text[self.idx..].starts_with(&word.chars().collect::<Vec<_>>()){
+                        self.idx += word.len();
+                        return Ok(value);
+                    }
+                }
+                Err(self.error("expected a value"))
+            },
+            None => Err(self.error("unexpected end of input")),
+        }
+    }
+
+    fn object(&mut self) -> Result<Value, JsonError>{
+        self.expect('{')?;
+        let mut fields = Vec::new();
+        self.skip_whitespace();
+        if self.text.get(self.idx) == Some(&'}'){
+            self.idx += 1;
+            return Ok(Value::Object(fields));
+        }
+        loop{
+            self.skip_whitespace();
+            if self.text.get(self.idx) != Some(&'"'){
+                return Err(self.error("expected a field name"));
+            }
+            let name = self.string()?;
+            self.expect(':')?;
+            self.skip_whitespace();
+            let offset = self.idx;
+            fields.push((name, offset, self.value()?));
+            self.skip_whitespace();
+            match self.text.get(self.idx){
+                Some(',') => self.idx += 1,
+                Some('}') => {self.idx += 1; return Ok(Value::Object(fields));},
+                _ => return Err(self.error("expected `,` or `}`")),
+            }
+        }
+    }
+
+    fn array(&mut self) -> Result<Value, JsonError>{
+        self.expect('[')?;
+        let mut items = Vec::new();
+        self.skip_whitespace();
+        if self.text.get(self.idx) == Some(&']'){
+            self.idx += 1;
+            return Ok(Value::Array(items));
+        }
+        loop{
+            self.skip_whitespace();
+            let offset = self.idx;
+            items.push((offset, self.value()?));
+            self.skip_whitespace();
+            match self.text.get(self.idx){
+                Some(',') => self.idx += 1,
+                Some(']') => {self.idx += 1; return Ok(Value::Array(items));},
+                _ => return Err(self.error("expected `,` or `]`")),
+            }
+        }
+    }
+
+    fn string(&mut self) -> Result<String, JsonError>{
+        self.idx += 1;
+        let mut s = String::new();
+        loop{
+            let c = match self.text.get(self.idx){
+                Some(c) => *c,
+                None => return Err(self.error("unterminated string")),
+            };
+            self.idx += 1;
+            match c{
+                '"' => return Ok(s),
+                '\\' => {
+                    let escaped = self.text.get(self.idx).copied();
+                    self.idx += 1;
+                    match escaped{
+                        Some('"') => s.push('"'),
+                        Some('\\') => s.push('\\'),
+                        Some('/') => s.push('/'),
+                        Some('b') => s.push('\u{8}'),
+                        Some('f') => s.push('\u{c}'),
+                        Some('n') => s.push('\n'),
+                        Some('r') => s.push('\r'),
+                        Some('t') => s.push('\t'),
+                        Some('u') => {
+                            let hex: String = self.text.iter().skip(self.idx).take(4).collect();
+                            let code = u32::from_str_radix(&hex, 16).ok().filter(|_| hex.len() == 4);
+                            match code.and_then(char::from_u32){
+                                Some(c) => s.push(c),
+                                None => return Err(self.error("invalid \\u escape")),
+                            }
+                            self.idx += 4;
+                        },
+                        _ => return Err(self.error("invalid escape")),
+                    }
+                },
+                c => s.push(c),
+            }
+        }
+    }
+
+    fn number(&mut self) -> Value{
+        let start = self.idx;
+        while self.idx < self.text.len() && (self.text[self.idx].is_ascii_digit() || "+-.eE".contains(self.text[self.idx])){
+            self.idx += 1;
+        }
+        Value::Number(self.text[start..self.idx].iter().collect())
+    }
+}
@@ -0,0 +1,528 @@
+use std::fmt;
+use std::collections::{HashMap, VecDeque};
+
+
+//key word: BEGIN END INTEGER REAL DIV MOD PROGRAM VAR
+
+lazy_static!{
+    static ref KeyWord: HashMap<&'static str, Token> = {
+        let mut m = HashMap::new();
+        m.insert("BEGIN", Token::KEYWORD("BEGIN".to_string()));
+        m.insert("END", Token::KEYWORD("END".to_string()));
+        m.insert("INTEGER", Token::KEYWORD("INTEGER".to_string()));
+        m.insert("REAL", Token::KEYWORD("REAL".to_string()));
+        m.insert("DIV", Token::KEYWORD("DIV".to_string()));
+        m.insert("MOD", Token::KEYWORD("MOD".to_string()));
+        m.insert("PROGRAM", Token::KEYWORD("PROGRAM".to_string()));
+        m.insert("VAR", Token::KEYWORD("VAR".to_string()));
+        m
+    };
+}
+
+
+#[derive(Clone)]
+pub enum Token{
+    EOF,
+    OP1(char),
+    OP2(char),
+    LP,
+    RP,
+    INTEGER_CONST(u64),
+    REAL_CONST(f64),
+    UNARY(char),
+    KEYWORD(String),
+    DOT,
+    DOTDOT,
+    ASSIGN,
+    SEMI,
+    COMMA,
+    COLON,
+    ID(String),
+    ASTNode(String),
+}
+
+
+//half-open range of char indices into the source text
+#[derive(Clone, Copy, Default, PartialEq, Debug)]
+pub struct Span{
+    pub start: usize,
+    pub end: usize,
+}
+
+//run-time checks switched by {$R}, {$Q} and {$I} directives
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Checks{
+    pub range: bool,
+    pub overflow: bool,
+    pub io: bool,
+}
+
+#[derive(Debug)]
+pub struct LexError{
+    pub span: Span,
+    pub message: String,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum TriviaKind{
+    //spaces and tabs
+    Whitespace,
+    //`\n` or `\r\n`
+    Newline,
+    Comment,
+    //a `{$...}` or `(*$...*)` comment
+    Directive,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Trivia{
+    pub kind: TriviaKind,
+    pub span: Span,
+}
+
+#[derive(Clone, Debug)]
+pub struct SpannedToken{
+    pub token: Token,
+    pub span: Span,
+    //checks in effect where the token starts
+    pub checks: Checks,
+    //whitespace and comments before the token, only filled in when trivia is preserved
+    pub leading: Vec<Trivia>,
+    //whitespace and comments after the token up to and including the end of its line
+    pub trailing: Vec<Trivia>,
+}
+
+//Turns source text into tokens. Yields every token including the final EOF,
+//then None. Lexing stops after the first error.
+pub struct Lexer{
+    text: Vec<char>,
+    idx: usize,
+    //checks in effect at idx, updated while skipping directives
+    checks: Checks,
+    nested_comments: bool,
+    preserve_trivia: bool,
+    lookahead: VecDeque<Result<SpannedToken, LexError>>,
+    finished: bool,
+}
+
+impl fmt::Debug for Token{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result{
+        match self{
+            Token::INTEGER_CONST(n) => write!(f, "INTEGER: {}", n),
+            Token::REAL_CONST(n) => write!(f, "REAL: {}", n),
+            Token::OP1(c) => write!(f, "operation: {}", c),
+            Token::OP2(c) => write!(f, "operation: {}", c),
+            Token::EOF => write!(f, "EOF"),
+            Token::LP => write!(f, "("),
+            Token::RP => write!(f, ")"),
+            Token::UNARY(c) => write!(f, "UNARY: {}", c),
+            Token::KEYWORD(s) => write!(f, "KEYWORD: {}", s),
+            Token::DOT => write!(f, "DOT"),
+            Token::DOTDOT => write!(f, "DOTDOT"),
+            Token::ID(s) => write!(f, "variable: {}", s),
+            Token::ASSIGN => write!(f, "ASSIGN"),
+            Token::SEMI => write!(f, "SEMI"),
+            Token::COMMA => write!(f, "COMMA"),
+            Token::COLON => write!(f, "COLON"),
+            Token::ASTNode(s) => write!(f, "ASTNode: {}", s),
+        }
+    }
+}
+
+impl PartialEq for Token{
+    fn eq(&self, other: &Token) -> bool{
+        match (self, other){
+            (Token::EOF, Token::EOF) => true,
+            (Token::OP1(_), Token::OP1(_)) => true,
+            (Token::OP2(_), Token::OP2(_)) => true,
+            (Token::LP, Token::LP) => true,
+            (Token::RP, Token::RP) => true,
+            (Token::INTEGER_CONST(_), Token::INTEGER_CONST(_)) => true,
+            (Token::REAL_CONST(_), Token::REAL_CONST(_)) => true,
+            (Token::UNARY(_), Token::UNARY(_)) => true,
+            (Token::KEYWORD(a), Token::KEYWORD(b)) => a == b,
+            (Token::DOT, Token::DOT) => true,
+            (Token::DOTDOT, Token::DOTDOT) => true,
+            (Token::ASSIGN, Token::ASSIGN) => true,
+            (Token::SEMI, Token::SEMI) => true,
+            (Token::COMMA, Token::COMMA) => true,
+            (Token::COLON, Token::COLON) => true,
+            (Token::ID(_), Token::ID(_)) => true,
+            (Token::ASTNode(a), Token::ASTNode(b)) => a == b,
+            _ => false,
+        }
+    }
+}
+
+impl Span{
+    pub fn new(start: usize, end: usize) -> Self{
+        Span{start, end}
+    }
+
+    //smallest span covering both self and other
+    pub fn to(self, other: Span) -> Span{
+        Span{start: self.start.min(other.start), end: self.end.max(other.end)}
+    }
+}
+
+impl Default for Checks{
+    fn default() -> Self{
+        Checks{range: true, overflow: true, io: true}
+    }
+}
+
+impl Checks{
+    //apply the switches of a directive body such as `R+,Q-`, unknown switches are ignored
+    pub fn apply(&mut self, directive: &str){
+        for switch in directive.split(',').map(|s| s.trim()){
+            let mut chars = switch.chars();
+            let (name, state) = match (chars.next(), chars.next(), chars.next()){
+                (Some(name), Some(state @ '+'), None) | (Some(name), Some(state @ '-'), None) => (name, state == '+'),
+                _ => continue,
+            };
+            match name.to_ascii_uppercase(){
+                'R' => self.range = state,
+                'Q' => self.overflow = state,
+                'I' => self.io = state,
+                _ => {},
+            }
+        }
+    }
+}
+
+impl LexError{
+    pub fn new(span: Span, message: String) -> Self{
+        LexError{span, message}
+    }
+}
+
+impl Lexer{
+    pub fn new(s: &str) -> Self{
+        Lexer{text: s.chars().collect(), idx: 0, checks: Checks::default(), nested_comments: false,
+              preserve_trivia: false, lookahead: VecDeque::new(), finished: false}
+    }
+
+    //start with `checks` in effect until the first directive changes them
+    pub fn with_checks(mut self, checks: Checks) -> Self{
+        self.checks = checks;
+        self
+    }
+
+    //let `{ { } }` and `(* (* *) *)` nest instead of ending at the first closing delimiter
+    pub fn nested_comments(mut self, on: bool) -> Self{
+        self.nested_comments = on;
+        self
+    }
+
+    //attach whitespace and comments to the tokens so the source can be rebuilt
+    //exactly with `reconstruct`
+    pub fn preserve_trivia(mut self, on: bool) -> Self{
+        self.preserve_trivia = on;
+        self
+    }
+
+    //the token `next` would return, without consuming it
+    pub fn peek(&mut self) -> Option<&Result<SpannedToken, LexError>>{
+        self.peek_nth(0)
+    }
+
+    //the token n places after the one `next` would return
+    pub fn peek_nth(&mut self, n: usize) -> Option<&Result<SpannedToken, LexError>>{
+        while self.lookahead.len() <= n && !self.finished{
+            let token = self.lex_token();
+            self.finished = matches!(token, Err(_) | Ok(SpannedToken{token: Token::EOF, ..}));
+            self.lookahead.push_back(token);
+        }
+        self.lookahead.get(n)
+    }
+
+    //keywords are matched case-insensitively, identifiers keep their spelling
+    fn _id(&mut self) -> Token{
+        let mut s: String = String::new();
+
+        while self.idx < self.text.len(){
+            match self.text[self.idx]{
+                c @ 'a'..='z' | c @ 'A'..='Z' | c @ '0'..='9' | c @ '_' => {
+                    s.push(c);
+                    self.idx += 1;
+                },
+                _ => break,
+            }
+        }
+        match KeyWord.get(s.to_ascii_uppercase().as_str()){
+            Some(value) => value.clone(),
+            None => Token::ID(s),
+        }
+    }
+
+    //123, 1_000, 1.5, 1.5E-3, 2e10, $FF, %1010, &17
+    fn get_digits(&mut self) -> Result<Token, LexError>{
+        let start = self.idx;
+        let (radix, name) = match self.text[start]{
+            '$' => (16, "hexadecimal"),
+            '%' => (2, "binary"),
+            '&' => (8, "octal"),
+            _ => (10, "decimal"),
+        };
+
+        if radix != 10{
+            self.idx += 1;
+            let digits = self.digit_run(radix);
+            if digits.is_empty(){
+                return Err(LexError::new(Span::new(start, self.idx), format!("expected {} digits after `{}`", name, self.text[start])));
+            }
+            self.literal_end(start, name)?;
+            return self.integer_literal(start, &digits, radix);
+        }
+
+        let mut num = self.digit_run(10);
+        let mut real = false;
+        if self.peek_char(0) == Some('.') && self.peek_char(1) != Some('.'){
+            //`1..10` is an integer followed by DOTDOT
+            self.idx += 1;
+            let fraction = self.digit_run(10);
+            if fraction.is_empty(){
+                return Err(LexError::new(Span::new(start, self.idx), "expected digits after the decimal point".to_string()));
+            }
+            num.push('.');
+            num += &fraction;
+            real = true;
+        }
+        if let Some('e') | Some('E') = self.peek_char(0){
+            self.idx += 1;
+            num.push('e');
+            if let Some(c @ '+') | Some(c @ '-') = self.peek_char(0){
+                self.idx += 1;
+                num.push(c);
+            }
+            let exponent = self.digit_run(10);
+            if exponent.is_empty(){
+                return Err(LexError::new(Span::new(start, self.idx), "expected digits in the exponent".to_string()));
+            }
+            num += &exponent;
+            real = true;
+        }
+        self.literal_end(start, name)?;
+
+        if real{
+            match num.parse::<f64>(){
+                Ok(n) if n.is_finite() => Ok(Token::REAL_CONST(n)),
+                _ => Err(LexError::new(Span::new(start, self.idx), "real literal is out of range".to_string())),
+            }
+        }else{
+            self.integer_literal(start, &num, 10)
+        }
+    }
+
+    //digits of `radix`, `_` is allowed as a separator between two digits
+    fn digit_run(&mut self, radix: u32) -> String{
+        let mut digits = String::new();
+        while let Some(c) = self.peek_char(0){
+            if c.is_digit(radix){
+                digits.push(c);
+            }else if c == '_' && !digits.is_empty() && self.peek_char(1).is_some_and(|d| d.is_digit(radix)){
+                //separator, not part of the value
+            }else{
+                break;
+            }
+            self.idx += 1;
+        }
+        digits
+    }
+
+    //a literal must not run straight into a letter or digit, e.g. `12ab` or `%102`
+    fn literal_end(&self, start: usize, name: &str) -> Result<(), LexError>{
+        match self.peek_char(0){
+            Some(c) if c.is_ascii_alphanumeric() || c == '_' =>
+                Err(LexError::new(Span::new(start, self.idx + 1), format!("invalid character `{}` in {} literal", c, name))),
+            _ => Ok(()),
+        }
+    }
+
+    fn integer_literal(&self, start: usize, digits: &str, radix: u32) -> Result<Token, LexError>{
+        match u64::from_str_radix(digits, radix){
+            Ok(n) if n <= i64::MAX as u64 => Ok(Token::INTEGER_CONST(n)),
+            _ => Err(LexError::new(Span::new(start, self.idx), format!("integer literal is out of range, the largest INTEGER is {}", i64::MAX))),
+        }
+    }
+
+    fn peek_char(&self, offset: usize) -> Option<char>{
+        self.text.get(self.idx + offset).cloned()
+    }
+
+    fn lex_token(&mut self) -> Result<SpannedToken, LexError>{
+        let leading = self.skip_whitespace(false)?;
+        let checks = self.checks;
+        let start = self.idx;
+        if self.idx >= self.text.len() {
+            return Ok(SpannedToken{token: Token::EOF, span: Span::new(start, start), checks, leading, trailing: Vec::new()});
+        }
+        let token = match self.text[self.idx]{
+                'a' ..= 'z' | 'A' ..= 'Z' | '_' => self._id(),
+                ':' => {
+                    if self.idx + 1 < self.text.len() && self.text[self.idx + 1] == '='{
+                        self.idx += 2;
+                        Token::ASSIGN
+                    }else{
+                        self.idx += 1;
+                        Token::COLON
+                    }
+                },
+                ';' => {
+                    self.idx += 1;
+                    Token::SEMI
+                },
+                ',' => {
+                    self.idx += 1;
+                    Token::COMMA
+                }
+                '.' => {
+                    if self.idx + 1 < self.text.len() && self.text[self.idx + 1] == '.'{
+                        self.idx += 2;
+                        Token::DOTDOT
+                    }else{
+                        self.idx += 1;
+                        Token::DOT
+                    }
+                },
+                '0'..='9' | '$' | '%' => self.get_digits()?,
+                '&' if self.peek_char(1).is_some_and(|c| c.is_digit(8)) => self.get_digits()?,
+                c @ '+' | c @ '-' => {self.idx += 1; Token::OP1(c)},
+                c @ '*' | c @ '/' => {self.idx += 1; Token::OP2(c)},
+                '(' => {self.idx += 1; Token::LP},
+                ')' => {self.idx += 1; Token::RP},
+                e => return Err(LexError::new(Span::new(start, start + 1), format!("unexpected character `{}`", e))),
+            };
+        let span = Span::new(start, self.idx);
+        let trailing = if self.preserve_trivia {self.skip_whitespace(true)?} else {Vec::new()};
+        Ok(SpannedToken{token, span, checks, leading, trailing})
+    }
+
+    //`trailing` stops after the first newline. The skipped trivia is only
+    //returned when it is preserved.
+    fn skip_whitespace(&mut self, trailing: bool) -> Result<Vec<Trivia>, LexError>{
+        let mut trivia = Vec::new();
+        while self.idx < self.text.len(){
+            let start = self.idx;
+            let kind = match self.text[self.idx]{
+                '\n' => {self.idx += 1; TriviaKind::Newline},
+                '\r' if self.peek_char(1) == Some('\n') => {self.idx += 2; TriviaKind::Newline},
+                ' ' | '\t' | '\r' => {
+                    while let Some(' ') | Some('\t') = self.peek_char(0){
+                        self.idx += 1;
+                    }
+                    if self.idx == start {self.idx += 1;}
+                    TriviaKind::Whitespace
+                },
+                _ if comment_start(&self.text, self.idx).is_some() => self.skip_comment()?,
+                _ => break,
+            };
+            if self.preserve_trivia{
+                trivia.push(Trivia{kind, span: Span::new(start, self.idx)});
+            }
+            if trailing && kind == TriviaKind::Newline{
+                break;
+            }
+        }
+        Ok(trivia)
+    }
+
+    fn skip_comment(&mut self) -> Result<TriviaKind, LexError>{
+        let start = self.idx;
+        let end = match comment_end(&self.text, start, self.nested_comments){
+            Some(end) => end,
+            None => return Err(LexError::new(Span::new(start, self.text.len()), "unterminated comment".to_string())),
+        };
+        self.idx = end;
+
+        //{$R+,Q-} or (*$R+,Q-*) style compiler directive
+        match comment_directive(&self.text[start..end]){
+            Some(directive) => {
+                self.checks.apply(&directive);
+                Ok(TriviaKind::Directive)
+            },
+            None => Ok(TriviaKind::Comment),
+        }
+    }
+}
+
+impl Iterator for Lexer{
+    type Item = Result<SpannedToken, LexError>;
+
+    fn next(&mut self) -> Option<Self::Item>{
+        self.peek();
+        self.lookahead.pop_front()
+    }
+}
+
+//Rebuild the source from tokens lexed with `preserve_trivia`. The result is
+//identical to `text` as long as every token, including EOF, is passed in.
+pub fn reconstruct(text: &str, tokens: &[SpannedToken]) -> String{
+    let chars: Vec<char> = text.chars().collect();
+    let mut ret = String::with_capacity(text.len());
+    for t in tokens.iter(){
+        let spans = t.leading.iter().map(|tr| tr.span)
+            .chain(std::iter::once(t.span))
+            .chain(t.trailing.iter().map(|tr| tr.span));
+        for span in spans{
+            ret.extend(chars[span.start..span.end].iter());
+        }
+    }
+    ret
+}
+
+//Pascal identifiers are case-insensitive, symbol tables are keyed by this form
+pub fn canonical(name: &str) -> String{
+    name.to_ascii_lowercase()
+}
+
+//length of the opening delimiter if a comment starts at idx
+pub fn comment_start(text: &[char], idx: usize) -> Option<usize>{
+    match (text.get(idx), text.get(idx + 1)){
+        (Some('{'), _) => Some(1),
+        (Some('('), Some('*')) | (Some('/'), Some('/')) => Some(2),
+        _ => None,
+    }
+}
+
+//index just past the comment that starts at idx, None if it is never closed.
+//A `//` comment ends with its line.
+pub fn comment_end(text: &[char], idx: usize, nested: bool) -> Option<usize>{
+    let (open, close): (&[char], &[char]) = match text[idx]{
+        '{' => (&['{'], &['}']),
+        '(' => (&['(', '*'], &['*', ')']),
+        _ => {
+            let len = text[idx..].iter().position(|c| *c == '\n').unwrap_or(text.len() - idx);
+            return Some(idx + len);
+        },
+    };
+
+    let mut depth = 1;
+    let mut i = idx + open.len();
+    while i < text.len(){
+        if text[i..].starts_with(close){
+            depth -= 1;
+            i += close.len();
+            if depth == 0 {return Some(i);}
+        }else if nested && text[i..].starts_with(open){
+            depth += 1;
+            i += open.len();
+        }else{
+            i += 1;
+        }
+    }
+    None
+}
+
+//body of a `{$...}` or `(*$...*)` comment
+pub fn comment_directive(comment: &[char]) -> Option<String>{
+    let (open, close) = match (comment.first(), comment.last()){
+        (Some('{'), Some('}')) => (1, 1),
+        (Some('('), Some(')')) => (2, 2),
+        _ => return None,
+    };
+    if comment.len() < open + close + 1 || comment[open] != '$'{
+        return None;
+    }
+    Some(comment[open + 1..comment.len() - close].iter().collect())
+}
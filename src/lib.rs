@@ -0,0 +1,780 @@
+#![allow(non_camel_case_types, non_snake_case, non_upper_case_globals, clippy::upper_case_acronyms)]
+
+#[macro_use]
+extern crate lazy_static;
+
+pub mod bytecode;
+pub mod debugger;
+pub mod dot;
+pub mod emit_asm;
+pub mod emit_c;
+pub mod format;
+pub mod ir;
+pub mod json;
+pub mod lexer;
+pub mod optimize;
+pub mod preprocess;
+pub mod repl;
+pub mod typecheck;
+pub mod visitor;
+pub mod wat;
+
+use std::convert::TryFrom;
+use std::fmt;
+use std::collections::{HashMap, VecDeque};
+
+use lexer::{canonical, Checks, LexError, Lexer, Span, Token};
+use preprocess::SourceMap;
+use typecheck::Type;
+
+//AST node type(Token::ASTNode): PROGRAM BLOCK VARDEC SUBRANGE Empty COMP
+
+//parser, builds the AST from the tokens of a Lexer
+pub struct Interpreter{
+    lexer: Lexer,
+    current_token: Token,
+    token_span: Span,
+    //checks in effect where the current token starts
+    token_checks: Checks,
+}
+
+#[derive(Debug)]
+pub struct ParseError{
+    pub span: Span,
+    pub message: String,
+}
+
+#[derive(Clone)]
+pub struct TreeNode{
+    pub node: Token,
+    pub sub_nodes: Vec<TreeNode>,
+    pub span: Span,
+    //static type, filled in by TypeChecker for expressions and assignment targets
+    pub ty: Option<Type>,
+    //checks active for this node, recorded on statements and operators
+    pub checks: Checks,
+}
+
+#[derive(Clone)]
+pub enum VarType{
+    Integer(i64),
+    Real(f64),
+}
+
+impl fmt::Debug for VarType{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result{
+        match self{
+            VarType::Integer(n) => write!(f, "INTEGER({})", n),
+            VarType::Real(n) => write!(f, "REAL({})", n),
+        }
+    }
+}
+
+
+
+
+impl ParseError{
+    fn new(span: Span, message: String) -> Self{
+        ParseError{span, message}
+    }
+
+    pub fn render(&self, map: &SourceMap) -> String{
+        format!("{}: syntax error: {}", map.describe(self.span), self.message)
+    }
+}
+
+impl From<LexError> for ParseError{
+    fn from(e: LexError) -> Self{
+        ParseError{span: e.span, message: e.message}
+    }
+}
+
+impl TreeNode{
+    pub fn new(node: Token, sub_nodes: Vec<TreeNode>, span: Span) -> Self{
+        TreeNode{node, sub_nodes, span, ty: None, checks: Checks::default()}
+    }
+
+    pub fn with_checks(mut self, checks: Checks) -> Self{
+        self.checks = checks;
+        self
+    }
+
+    //same node kinds and values in the same tree layout, spans and annotations are ignored
+    pub fn same_shape(&self, other: &TreeNode) -> bool{
+        format!("{:?}", self.node) == format!("{:?}", other.node)
+            && self.sub_nodes.len() == other.sub_nodes.len()
+            && self.sub_nodes.iter().zip(other.sub_nodes.iter()).all(|(a, b)| a.same_shape(b))
+    }
+
+    //short text of the node itself, without its children
+    pub fn label(&self) -> String{
+        match &self.node{
+            Token::ASTNode(s) => s.clone(),
+            Token::ASSIGN => ":=".to_string(),
+            Token::OP1(c) | Token::OP2(c) => c.to_string(),
+            Token::UNARY(c) => format!("unary {}", c),
+            Token::KEYWORD(s) => s.clone(),
+            Token::INTEGER_CONST(n) => n.to_string(),
+            Token::REAL_CONST(n) => format!("{:?}", n),
+            Token::ID(s) => s.clone(),
+            token => format!("{:?}", token),
+        }
+    }
+}
+
+impl VarType{
+    fn as_i64(&self) -> i64{
+        match self{
+            VarType::Integer(n) => *n,
+            VarType::Real(n) => *n as i64,
+        }
+    }
+
+    fn as_f64(&self) -> f64{
+        match self{
+            VarType::Integer(n) => *n as f64,
+            VarType::Real(n) => *n,
+        }
+    }
+}
+
+impl PartialEq for VarType{
+    fn eq(&self, other: &VarType) -> bool{
+        matches!((self, other), (VarType::Integer(_), VarType::Integer(_)) | (VarType::Real(_), VarType::Real(_)))
+    }
+}
+
+
+impl Interpreter{
+    pub fn new(lexer: Lexer) -> Self{
+        Interpreter{lexer, current_token: Token::EOF, token_span: Span::default(), token_checks: Checks::default()}
+    }
+
+    //move to the next token of the lexer
+    fn get_next_token(&mut self) -> Result<Token, LexError>{
+        match self.lexer.next(){
+            Some(Ok(t)) => {
+                self.current_token = t.token;
+                self.token_span = t.span;
+                self.token_checks = t.checks;
+            },
+            Some(Err(e)) => return Err(e),
+            //only reached when the parser looks past EOF
+            None => self.current_token = Token::EOF,
+        }
+        Ok(self.current_token.clone())
+    }
+
+    //consume the current token and return its span
+    fn eat(&mut self, token: Token) -> Result<Span, ParseError>{
+        if self.current_token == token{
+            let span = self.token_span;
+            self.get_next_token()?;
+            Ok(span)
+        }else{
+            Err(self.unexpected(&format!("{:?}", token)))
+        }
+    }
+
+    fn unexpected(&self, expected: &str) -> ParseError{
+        ParseError::new(self.token_span, format!("expected {}, found {:?}", expected, self.current_token))
+    }
+
+    fn program(&mut self) -> Result<TreeNode, ParseError>{
+        let start = self.eat(Token::KEYWORD("PROGRAM".to_string()))?;
+        if self.current_token != Token::ID("a".to_string()){
+            return Err(self.unexpected("program name"));
+        }
+        let name = self.variable()?;
+        self.eat(Token::SEMI)?;
+        let block = self.block()?;
+        let end = self.eat(Token::DOT)?;
+        Ok(TreeNode::new(Token::ASTNode("PROGRAM".to_string()), vec![name, block], start.to(end)))
+    }
+
+    fn block(&mut self) -> Result<TreeNode, ParseError>{
+        let mut s_nodes = self.declarations()?;
+        s_nodes.push(self.compound_statement()?);
+        let span = s_nodes[0].span.to(s_nodes[s_nodes.len() - 1].span);
+        Ok(TreeNode::new(Token::ASTNode("BLOCK".to_string()), s_nodes, span))
+    }
+
+    fn declarations(&mut self) -> Result<Vec<TreeNode>, ParseError>{
+        let mut nodes: Vec<TreeNode> = Vec::new();
+        if self.current_token == Token::KEYWORD("VAR".to_string()){
+            self.eat(Token::KEYWORD("VAR".to_string()))?;
+            while self.current_token == Token::ID("a".to_string()){
+                nodes.extend(self.variable_declaration()?);
+                self.eat(Token::SEMI)?;
+            }
+        }
+
+        Ok(nodes)
+    }
+
+    fn variable_declaration(&mut self) -> Result<Vec<TreeNode>, ParseError>{
+        let mut vars: Vec<(String, Span)> = Vec::new();
+
+        loop{
+            let token = self.current_token.clone();
+            match token{
+                Token::COLON => {
+                    self.get_next_token()?;
+                    break;
+                },
+                Token::ID(s) => {vars.push((s, self.token_span))},
+                Token::COMMA => {},
+                _ => return Err(self.unexpected("variable name, COMMA or COLON")),
+            }
+            self.get_next_token()?;
+        }
+
+        let type_node = self.type_spec()?;
+
+        let mut ret: Vec<TreeNode> = Vec::new();
+        for (var, span) in vars{
+            let node1 = TreeNode::new(Token::ID(var), vec![], span);
+            let span = span.to(type_node.span);
+            ret.push(TreeNode::new(Token::ASTNode("VARDEC".to_string()), vec![node1, type_node.clone()], span));
+        }
+        Ok(ret)
+    }
+
+    //INTEGER | REAL | constant DOTDOT constant
+    fn type_spec(&mut self) -> Result<TreeNode, ParseError>{
+        match &self.current_token{
+            Token::KEYWORD(keyword) if keyword == "INTEGER" || keyword == "REAL" => self.variable(),
+            Token::OP1(_) | Token::INTEGER_CONST(_) => {
+                let low = self.subrange_bound()?;
+                self.eat(Token::DOTDOT)?;
+                let high = self.subrange_bound()?;
+                let span = low.span.to(high.span);
+                Ok(TreeNode::new(Token::ASTNode("SUBRANGE".to_string()), vec![low, high], span))
+            },
+            _ => Err(self.unexpected("INTEGER, REAL or a subrange")),
+        }
+    }
+
+    fn subrange_bound(&mut self) -> Result<TreeNode, ParseError>{
+        let token = self.current_token.clone();
+        let span = self.token_span;
+        match token{
+            Token::OP1(c) => {
+                self.get_next_token()?;
+                let operand = self.subrange_bound()?;
+                let span = span.to(operand.span);
+                Ok(TreeNode::new(Token::UNARY(c), vec![operand], span))
+            },
+            Token::INTEGER_CONST(_) => self.variable(),
+            _ => Err(self.unexpected("integer constant")),
+        }
+    }
+
+    fn compound_statement(&mut self) -> Result<TreeNode, ParseError>{
+        let start = self.eat(Token::KEYWORD("BEGIN".to_string()))?;
+        let nodes: Vec<TreeNode> = self.statement_list()?;
+        let end = self.eat(Token::KEYWORD("END".to_string()))?;
+        Ok(TreeNode::new(Token::ASTNode("COMP".to_string()), nodes, start.to(end)))
+    }
+
+    fn statement_list(&mut self) -> Result<Vec<TreeNode>, ParseError>{
+        let mut nodes: Vec<TreeNode> = vec![self.statement()?];
+        while self.current_token == Token::SEMI{
+            self.eat(Token::SEMI)?;
+            nodes.push(self.statement()?);
+        }
+
+        if self.current_token == Token::ID("a".to_string()){
+            return Err(self.unexpected("SEMI"));
+        }
+        Ok(nodes)
+    }
+
+    fn statement(&mut self) -> Result<TreeNode, ParseError>{
+        match &self.current_token{
+            Token::KEYWORD(keyword) if keyword.as_str() == "BEGIN" => self.compound_statement(),
+            Token::ID(_) => self.assignment_statement(),
+            _ => Ok(self.empty()),
+        }
+    }
+
+    fn assignment_statement(&mut self) -> Result<TreeNode, ParseError>{
+        let checks = self.token_checks;
+        let left = self.variable()?;
+        let token = self.current_token.clone();
+        self.eat(Token::ASSIGN)?;
+        let right = self.expr()?;
+        let span = left.span.to(right.span);
+        Ok(TreeNode::new(token, vec![left, right], span).with_checks(checks))
+    }
+
+    fn variable(&mut self) -> Result<TreeNode, ParseError>{
+        let node = TreeNode::new(self.current_token.clone(), vec![], self.token_span);
+        self.get_next_token()?;
+        Ok(node)
+    }
+
+    fn empty(&mut self) -> TreeNode{
+        let span = Span::new(self.token_span.start, self.token_span.start);
+        TreeNode::new(Token::ASTNode("Empty".to_string()), vec![], span)
+    }
+
+
+    fn factor(&mut self) -> Result<TreeNode, ParseError>{
+        let token = self.current_token.clone();
+        let span = self.token_span;
+        let checks = self.token_checks;
+        match token{
+            Token::OP1(c) => {
+                self.get_next_token()?;
+                let operand = self.factor()?;
+                let span = span.to(operand.span);
+                Ok(TreeNode::new(Token::UNARY(c), vec![operand], span).with_checks(checks))
+            },
+            Token::INTEGER_CONST(_) | Token::REAL_CONST(_) => {
+                self.get_next_token()?;
+                Ok(TreeNode::new(token, vec![], span))
+            },
+            Token::LP => {
+                self.get_next_token()?;
+                let node = self.expr()?;
+                self.eat(Token::RP)?;
+                Ok(node)
+            },
+            Token::ID(_) =>{
+                self.variable()
+            },
+            _ => Err(self.unexpected("expression")),
+        }
+    }
+
+    fn term(&mut self) -> Result<TreeNode, ParseError>{
+        let mut node = self.factor()?;
+        loop{
+            match &self.current_token{
+                Token::KEYWORD(keyword) if keyword.as_str() == "DIV" || keyword.as_str() == "MOD" => {},
+                Token::OP2(_) => {},
+                _ => break,
+            }
+            let token = self.current_token.clone();
+            let checks = self.token_checks;
+            self.get_next_token()?;
+            let right = self.factor()?;
+            let span = node.span.to(right.span);
+            node = TreeNode::new(token, vec![node, right], span).with_checks(checks);
+        }
+        Ok(node)
+    }
+
+    fn expr(&mut self) -> Result<TreeNode, ParseError>{
+        let mut node = self.term()?;
+       
+        while let Token::OP1(_) = &self.current_token{
+            let token = self.current_token.clone();
+            let checks = self.token_checks;
+            self.get_next_token()?;
+            let right = self.term()?;
+            let span = node.span.to(right.span);
+            node = TreeNode::new(token, vec![node, right], span).with_checks(checks);
+        }
+        Ok(node)
+    }
+
+    pub fn parse(&mut self) -> Result<TreeNode, ParseError>{
+        self.get_next_token()?;
+        let node = self.program()?;
+        if self.current_token != Token::EOF{
+            return Err(self.unexpected("end of input after the final DOT"));
+        }
+        Ok(node)
+    }
+
+    //`VAR a, b : INTEGER; c : REAL` on its own, the last SEMI may be left out
+    pub fn parse_declarations(&mut self) -> Result<Vec<TreeNode>, ParseError>{
+        self.get_next_token()?;
+        self.eat(Token::KEYWORD("VAR".to_string()))?;
+        let mut nodes = Vec::new();
+        loop{
+            nodes.extend(self.variable_declaration()?);
+            if self.current_token == Token::EOF{
+                break;
+            }
+            self.eat(Token::SEMI)?;
+            if self.current_token == Token::EOF{
+                break;
+            }
+        }
+        Ok(nodes)
+    }
+
+    //statements separated by SEMI, returned as one COMP node
+    pub fn parse_statements(&mut self) -> Result<TreeNode, ParseError>{
+        self.get_next_token()?;
+        let nodes = self.statement_list()?;
+        if self.current_token != Token::EOF{
+            return Err(self.unexpected("SEMI or end of input"));
+        }
+        let span = nodes[0].span.to(nodes[nodes.len() - 1].span);
+        Ok(TreeNode::new(Token::ASTNode("COMP".to_string()), nodes, span))
+    }
+
+    pub fn parse_expression(&mut self) -> Result<TreeNode, ParseError>{
+        self.get_next_token()?;
+        let node = self.expr()?;
+        if self.current_token != Token::EOF{
+            return Err(self.unexpected("operator or end of input"));
+        }
+        Ok(node)
+    }
+}
+
+
+impl fmt::Debug for TreeNode{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result{
+        let mut q: VecDeque<&TreeNode> = VecDeque::new();
+        q.push_back(self);
+
+        let mut pre_level_num;
+        let mut this_level_num = 1;
+
+        let mut ret: String = String::new();
+
+        while !q.is_empty(){
+            pre_level_num = this_level_num;
+            this_level_num = 0;
+            for _ in 0..pre_level_num{
+                let node = q.pop_front().unwrap();
+                match node.node{
+                    Token::ID(_) | Token::INTEGER_CONST(_) | Token::REAL_CONST(_) => 
+                      ret += format!("  |{:?}(0)|  ", node.node).as_str(),
+                    _ => {
+                        ret += format!("  |{:?}({})|  ", node.node, node.sub_nodes.len()).as_str();
+                        for n in node.sub_nodes.iter(){
+                            q.push_back(n);
+                            this_level_num += 1;
+                        }
+                    }
+                }
+            }
+            ret.push('\n');
+        }
+
+        write!(f, "{}", ret)
+    }
+}
+
+//Indented tree, one node per line:
+//  Program Part10AST
+//  └── Block
+//      ├── VarDecl a: INTEGER
+//  ...
+//`{:#}` adds the span of every node.
+impl fmt::Display for TreeNode{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result{
+        self.write_tree(f, "", "", f.alternate())
+    }
+}
+
+impl TreeNode{
+    //`prefix` goes before this node's line, `indent` before the lines of its children
+    fn write_tree(&self, f: &mut fmt::Formatter, prefix: &str, indent: &str, spans: bool) -> fmt::Result{
+        let (text, children) = self.tree_line();
+        write!(f, "{}{}", prefix, text)?;
+        if spans{
+            write!(f, " @{}..{}", self.span.start, self.span.end)?;
+        }
+        writeln!(f)?;
+
+        for (i, node) in children.iter().enumerate(){
+            if i + 1 == children.len(){
+                node.write_tree(f, &format!("{}└── ", indent), &format!("{}    ", indent), spans)?;
+            }else{
+                node.write_tree(f, &format!("{}├── ", indent), &format!("{}│   ", indent), spans)?;
+            }
+        }
+        Ok(())
+    }
+
+    //the line of a node and the children listed below it
+    fn tree_line(&self) -> (String, &[TreeNode]){
+        match &self.node{
+            Token::ASTNode(s) if s == "PROGRAM" => (format!("Program {}", self.sub_nodes[0].label()), &self.sub_nodes[1..]),
+            Token::ASTNode(s) if s == "BLOCK" => ("Block".to_string(), &self.sub_nodes),
+            Token::ASTNode(s) if s == "VARDEC" => {
+                let ty = &self.sub_nodes[1];
+                let ty = match &ty.node{
+                    Token::ASTNode(_) => format!("{}..{}", ty.sub_nodes[0].expr_text(), ty.sub_nodes[1].expr_text()),
+                    _ => ty.label(),
+                };
+                (format!("VarDecl {}: {}", self.sub_nodes[0].label(), ty), &[])
+            },
+            Token::ASTNode(s) if s == "SUBRANGE" => ("Subrange".to_string(), &self.sub_nodes),
+            Token::ASTNode(s) if s == "COMP" => ("Compound".to_string(), &self.sub_nodes),
+            Token::ASTNode(s) if s == "Empty" => ("NoOp".to_string(), &[]),
+            Token::ASSIGN => ("Assign".to_string(), &self.sub_nodes),
+            Token::ID(_) => (format!("Var {}", self.label()), &[]),
+            Token::INTEGER_CONST(_) | Token::REAL_CONST(_) => (format!("Num {}", self.label()), &[]),
+            Token::UNARY(c) => (format!("UnaryOp {}", c), &self.sub_nodes),
+            Token::OP1(_) | Token::OP2(_) | Token::KEYWORD(_) if self.sub_nodes.len() == 2 => (format!("BinOp {}", self.label()), &self.sub_nodes),
+            _ => (self.label(), &self.sub_nodes),
+        }
+    }
+
+    //subrange bounds are constants, optionally signed
+    fn expr_text(&self) -> String{
+        match &self.node{
+            Token::UNARY(c) => format!("{}{}", c, self.sub_nodes[0].expr_text()),
+            _ => self.label(),
+        }
+    }
+}
+
+
+
+
+pub struct Visit{
+    //keyed by canonical name
+    pub var_table: HashMap<String, Option<VarType>>,
+    //declared spelling, in declaration order
+    pub var_names: Vec<String>,
+}
+
+//Called by `Visit::visit_with_hook` before each statement it executes,
+//compound statements included and empty ones not. `depth` is 0 for the
+//statements of the program body and one more inside each BEGIN ... END.
+//An error stops the program as a runtime error would.
+pub trait StatementHook{
+    fn before_statement(&mut self, visit: &mut Visit, statement: &TreeNode, depth: usize) -> Result<(), RuntimeError>;
+}
+
+#[derive(Debug)]
+pub struct RuntimeError{
+    pub span: Span,
+    pub message: String,
+}
+
+impl RuntimeError{
+    fn new(span: Span, message: String) -> Self{
+        RuntimeError{span, message}
+    }
+
+    pub fn render(&self, map: &SourceMap) -> String{
+        format!("{}: runtime error: {}", map.describe(self.span), self.message)
+    }
+}
+
+impl Default for Visit{
+    fn default() -> Self{
+        Visit::new()
+    }
+}
+
+impl Visit{
+    
+    pub fn new() -> Self{
+        Visit{var_table: HashMap::new(), var_names: Vec::new()}
+    }
+
+    // PROGRAM BLOCK VARDEC Empty COMP
+    pub fn visit(&mut self, root: &TreeNode) -> Result<(), RuntimeError>{
+        self.visit_program(root, &mut None)
+    }
+
+    //`visit` with `hook` called before every statement
+    pub fn visit_with_hook(&mut self, root: &TreeNode, hook: &mut dyn StatementHook) -> Result<(), RuntimeError>{
+        self.visit_program(root, &mut Some(hook))
+    }
+
+    fn visit_program(&mut self, root: &TreeNode, hook: &mut Option<&mut dyn StatementHook>) -> Result<(), RuntimeError>{
+        if root.node == Token::ASTNode("PROGRAM".to_string()) && root.sub_nodes[1].node == Token::ASTNode("BLOCK".to_string()){
+            for node in root.sub_nodes[1].sub_nodes.iter(){
+                self.visit_block(node, hook)?;
+            }
+            Ok(())
+        }else{
+            panic!("error in fn visit");
+        }
+    }
+
+    //the parts of a program one at a time, for the REPL
+    pub fn declare(&mut self, decl: &TreeNode){
+        self.visit_VarDec(decl);
+    }
+
+    pub fn execute(&mut self, statement: &TreeNode) -> Result<(), RuntimeError>{
+        match &statement.node{
+            Token::ASSIGN => self.visit_assign(statement),
+            Token::ASTNode(s) if s == "COMP" => self.visit_comp(statement, &mut None, 0),
+            Token::ASTNode(s) if s == "Empty" => Ok(()),
+            _ => panic!("error in fn execute, wrong statement: {:?}", statement.node),
+        }
+    }
+
+    pub fn evaluate(&mut self, expr: &TreeNode) -> Result<VarType, RuntimeError>{
+        self.visit_var(expr)
+    }
+
+    fn visit_block(&mut self, root: &TreeNode, hook: &mut Option<&mut dyn StatementHook>) -> Result<(), RuntimeError>{
+        match &root.node{
+            Token::ASTNode(s) if s == "VARDEC" => {self.visit_VarDec(root); Ok(())},
+            Token::ASTNode(s) if s == "COMP" => self.visit_comp(root, hook, 0),
+            _ => {panic!("error in fn visit_block, wrong AST node: {:?}", root.node)},
+        }
+    }
+
+    fn visit_VarDec(&mut self, root: &TreeNode){
+        //println!("visit node: {:?}", &root.node);
+        let var_name = match &root.sub_nodes[0].node{
+            Token::ID(s) => s.clone(),
+            _ => panic!("error in fn visit_VarDec, wrong var_name. current token: {:?}", root.node),
+        };
+
+        if self.var_table.contains_key(&canonical(&var_name)){panic!("error: {:?} has been declared!", var_name);}
+        self.var_table.insert(canonical(&var_name), None);
+        self.var_names.push(var_name);
+    }
+
+    //`depth` is the number of BEGIN ... END around the statements of `root`
+    //inside the program body
+    fn visit_comp(&mut self, root: &TreeNode, hook: &mut Option<&mut dyn StatementHook>, depth: usize) -> Result<(), RuntimeError>{
+        for node in root.sub_nodes.iter(){
+            if let (Some(hook), false) = (hook.as_deref_mut(), node.node == Token::ASTNode("Empty".to_string())){
+                hook.before_statement(self, node, depth)?;
+            }
+            match &node.node{
+                Token::ASSIGN => {self.visit_assign(node)?},
+                Token::ASTNode(s) if s == "COMP" => {self.visit_comp(node, hook, depth + 1)?},
+                Token::ASTNode(s) if s == "Empty" => {},
+                _ => panic!("error in fn visit_comp, wrong statement: {:?}", node.node),
+            }
+        }
+        Ok(())
+    }
+
+    fn visit_assign(&mut self, root: &TreeNode) -> Result<(), RuntimeError>{
+        let var_name = match &root.sub_nodes[0].node{
+            Token::ID(s) => s.clone(),
+            _ => panic!("error in fn visit_assign, wrong var_name: {:?}", root.sub_nodes[0].node),
+        };
+        let var_type = root.sub_nodes[0].ty.expect("error in fn visit_assign, tree has not been type checked");
+
+        let value = self.visit_var(&root.sub_nodes[1])?;
+        
+        //the checker has already rejected incompatible assignments, only widening and range checks are left
+        let value = match (var_type, &value){
+            (Type::Integer, VarType::Integer(n)) => VarType::Integer(*n),
+            (Type::Subrange(low, high), VarType::Integer(n)) => {
+                if root.checks.range && (*n < low || *n > high){
+                    return Err(RuntimeError::new(root.sub_nodes[1].span, format!("value {} out of range {}..{} of variable `{}`", n, low, high, var_name)));
+                }
+                VarType::Integer(*n)
+            },
+            (Type::Real, VarType::Integer(n)) => VarType::Real(*n as f64),
+            (Type::Real, VarType::Real(n)) => VarType::Real(*n),
+            _ => panic!("type miss match, variable {}, expect {}, found {:?}", var_name, var_type, value),
+        };
+        *self.var_table.get_mut(&canonical(&var_name)).unwrap() = Some(value);
+        Ok(())
+    }
+
+    fn visit_var(&mut self, root: &TreeNode) -> Result<VarType, RuntimeError>{
+        //println!("visit node: {:?}", &root.node);
+        let value = match &root.node{
+            Token::INTEGER_CONST(n)  => {
+                match i64::try_from(*n){
+                    Ok(n) => VarType::Integer(n),
+                    Err(_) => return Err(RuntimeError::new(root.span, format!("integer constant {} is too large", n))),
+                }
+            },
+            Token::REAL_CONST(n) => {
+                VarType::Real(*n)
+            },
+            Token::KEYWORD(s) if s == "DIV" || s == "MOD" => {
+                let left = self.visit_var(&root.sub_nodes[0])?.as_i64();
+                let right = self.visit_var(&root.sub_nodes[1])?.as_i64();
+                if right == 0{
+                    return Err(RuntimeError::new(root.sub_nodes[1].span, "division by zero".to_string()));
+                }
+                if s == "DIV"{
+                    self.integer_result(root, left.checked_div(right), left.wrapping_div(right))?
+                }else{
+                    //ISO 7185: j <= 0 is an error, otherwise the result lies in 0..j-1
+                    if right < 0{
+                        return Err(RuntimeError::new(root.sub_nodes[1].span, format!("MOD by negative value {}", right)));
+                    }
+                    VarType::Integer(left.rem_euclid(right))
+                }
+            },
+            Token::OP1(c) | Token::OP2(c) => {
+                let left = self.visit_var(&root.sub_nodes[0])?;
+                let right = self.visit_var(&root.sub_nodes[1])?;
+
+                match root.ty{
+                    Some(Type::Real) => {
+                        let (a, b) = (left.as_f64(), right.as_f64());
+                        if *c == '/' && b == 0.0{
+                            return Err(RuntimeError::new(root.sub_nodes[1].span, "division by zero".to_string()));
+                        }
+                        VarType::Real(operation(*c, a, b))
+                    },
+                    Some(_) => {
+                        let (a, b) = (left.as_i64(), right.as_i64());
+                        let (checked, wrapped) = match c{
+                            '+' => (a.checked_add(b), a.wrapping_add(b)),
+                            '-' => (a.checked_sub(b), a.wrapping_sub(b)),
+                            '*' => (a.checked_mul(b), a.wrapping_mul(b)),
+                            _ => panic!("wrong integer operation: {}", c),
+                        };
+                        self.integer_result(root, checked, wrapped)?
+                    },
+                    None => panic!("error in fn visit_var, tree has not been type checked"),
+                }
+            },
+            Token::UNARY(c) => {
+                match c{
+                    '+' => {self.visit_var(&root.sub_nodes[0])?},
+                    '-' => {
+                        match self.visit_var(&root.sub_nodes[0])?{
+                            VarType::Integer(n) => self.integer_result(root, n.checked_neg(), n.wrapping_neg())?,
+                            VarType::Real(n) => VarType::Real(-n),
+                        }
+                    },
+                    _ => panic!("can not recgnize UNARY: {:?}", c),
+                }
+                
+            }
+            Token::ID(s) => {
+                match self.var_table.get(&canonical(s)).unwrap_or_else(|| panic!("varialbe {} has not been declared!", s)){
+                    None => return Err(RuntimeError::new(root.span, format!("variable `{}` has not been initialized", s))),
+                    Some(v) => v.clone(),
+                }
+            },
+            _ => panic!("error in fn visit_var, wrong node: {:?}", root.node),
+        };
+
+        match value{
+            VarType::Real(n) if !n.is_finite() => Err(RuntimeError::new(root.span, format!("invalid floating point result {}", n))),
+            v => Ok(v),
+        }
+    }
+
+    //`checked` is None when the operation overflowed
+    fn integer_result(&self, root: &TreeNode, checked: Option<i64>, wrapped: i64) -> Result<VarType, RuntimeError>{
+        match checked{
+            Some(n) => Ok(VarType::Integer(n)),
+            None if root.checks.overflow => Err(RuntimeError::new(root.span, "integer overflow".to_string())),
+            None => Ok(VarType::Integer(wrapped)),
+        }
+    }
+}
+
+fn operation<T>(op: char, a: T, b: T,) -> T
+where T: std::ops::Add<Output=T> + std::ops::Sub<Output=T> + std::ops::Mul<Output=T> + std::ops::Div<Output=T>{
+    match op{
+        '+' => {a + b},
+        '-' => {a - b},
+        '*' => {a * b},
+        '/' => {a / b},
+        _ => {panic!("wrong operation: {}", op)},
+    }
+}
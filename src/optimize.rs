@@ -0,0 +1,130 @@
+use std::convert::Infallible;
+
+use super::lexer::Token;
+use super::typecheck::Type;
+use super::visitor::VisitorMut;
+use super::{TreeNode, VarType, Visit};
+
+//Rewrites a type checked program into one that computes the same values:
+//  - operators on constants are folded, unless evaluating them is a runtime
+//    error, which is then left to happen at run time
+//  - x * 1, 1 * x, x / 1 and x - 0 become x, as do x + 0 and 0 + x on INTEGER
+//    (on REAL, -0.0 + 0 is 0.0), but only when x already has the type of the
+//    whole expression, so 4 / 1 stays REAL
+//  - unary plus is dropped
+//  - empty statements are removed
+pub fn optimize(root: &mut TreeNode){
+    let Ok(()) = Optimizer.visit_node(root);
+}
+
+struct Optimizer;
+
+impl VisitorMut for Optimizer{
+    type Output = ();
+    type Error = Infallible;
+
+    //subrange bounds are left as written
+    fn visit_var_decl(&mut self, _node: &mut TreeNode) -> Result<(), Infallible>{
+        Ok(())
+    }
+
+    fn visit_compound(&mut self, node: &mut TreeNode) -> Result<(), Infallible>{
+        self.walk_compound(node)?;
+        node.sub_nodes.retain(|n| !matches!(&n.node, Token::ASTNode(s) if s == "Empty"
+            || (s == "COMP" && n.sub_nodes.is_empty())));
+        Ok(())
+    }
+
+    fn visit_block(&mut self, node: &mut TreeNode) -> Result<(), Infallible>{
+        self.walk_block(node)?;
+        //the body of the program keeps one statement
+        if let Some(body) = node.sub_nodes.last_mut(){
+            if body.sub_nodes.is_empty(){
+                body.sub_nodes.push(TreeNode::new(Token::ASTNode("Empty".to_string()), Vec::new(), body.span));
+            }
+        }
+        Ok(())
+    }
+
+    fn visit_binary_op(&mut self, node: &mut TreeNode) -> Result<(), Infallible>{
+        self.walk_binary_op(node)?;
+        if !fold(node){
+            simplify(node);
+        }
+        Ok(())
+    }
+
+    fn visit_unary_op(&mut self, node: &mut TreeNode) -> Result<(), Infallible>{
+        self.walk_unary_op(node)?;
+        if matches!(node.node, Token::UNARY('+')){
+            *node = node.sub_nodes.remove(0);
+        }else if !is_literal(&node.sub_nodes[0]){
+            fold(node);
+        }
+        Ok(())
+    }
+}
+
+//replace an operator on constants with its value, false if it is not constant
+//or can not be evaluated
+fn fold(node: &mut TreeNode) -> bool{
+    if !node.sub_nodes.iter().all(is_constant){
+        return false;
+    }
+    let value = match Visit::new().evaluate(node){
+        Ok(value) => value,
+        Err(_) => return false,
+    };
+    let ty = node.ty;
+    let (literal, negative) = match value{
+        VarType::Integer(n) if n == i64::MIN => return false,
+        VarType::Integer(n) => (Token::INTEGER_CONST(n.unsigned_abs()), n < 0),
+        VarType::Real(n) => (Token::REAL_CONST(n.abs()), n.is_sign_negative()),
+    };
+
+    let mut constant = TreeNode::new(literal, Vec::new(), node.span).with_checks(node.checks);
+    constant.ty = ty;
+    if negative{
+        constant = TreeNode::new(Token::UNARY('-'), vec![constant], node.span).with_checks(node.checks);
+        constant.ty = ty;
+    }
+    *node = constant;
+    true
+}
+
+fn simplify(node: &mut TreeNode){
+    let ty = node.ty.map(Type::base);
+    let same_type = |n: &TreeNode| n.ty.map(Type::base) == ty;
+    let integer = ty == Some(Type::Integer);
+    let (left, right) = (&node.sub_nodes[0], &node.sub_nodes[1]);
+
+    let keep = match node.node{
+        Token::OP2('*') if is_one(right) && same_type(left) => Some(0),
+        Token::OP2('*') if is_one(left) && same_type(right) => Some(1),
+        Token::OP2('/') if is_one(right) && same_type(left) => Some(0),
+        Token::OP1('-') if is_zero(right) && same_type(left) => Some(0),
+        Token::OP1('+') if integer && is_zero(right) && same_type(left) => Some(0),
+        Token::OP1('+') if integer && is_zero(left) && same_type(right) => Some(1),
+        _ => None,
+    };
+    if let Some(i) = keep{
+        *node = node.sub_nodes.remove(i);
+    }
+}
+
+fn is_literal(node: &TreeNode) -> bool{
+    matches!(node.node, Token::INTEGER_CONST(_) | Token::REAL_CONST(_))
+}
+
+//a literal, or a negated literal as left by folding
+fn is_constant(node: &TreeNode) -> bool{
+    is_literal(node) || (matches!(node.node, Token::UNARY(_)) && is_constant(&node.sub_nodes[0]))
+}
+
+fn is_one(node: &TreeNode) -> bool{
+    matches!(node.node, Token::INTEGER_CONST(1)) || matches!(node.node, Token::REAL_CONST(n) if n == 1.0)
+}
+
+fn is_zero(node: &TreeNode) -> bool{
+    matches!(node.node, Token::INTEGER_CONST(0)) || matches!(node.node, Token::REAL_CONST(n) if n == 0.0)
+}
@@ -0,0 +1,303 @@
+use std::fmt;
+use std::fs;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+use super::lexer::{comment_directive, comment_end, comment_start, Span};
+
+//files nested deeper than this are rejected, a chain of distinct files
+//can otherwise run away
+const MAX_INCLUDE_DEPTH: usize = 16;
+
+struct SourceFile{
+    name: String,
+    text: Vec<char>,
+}
+
+//`len` chars of the preprocessed text starting at `out_start` were copied
+//from `file` starting at `file_start`
+struct Segment{
+    out_start: usize,
+    file: usize,
+    file_start: usize,
+    len: usize,
+}
+
+//Maps char offsets of the preprocessed text back to the file they came from.
+pub struct SourceMap{
+    files: Vec<SourceFile>,
+    segments: Vec<Segment>,
+}
+
+pub struct Source{
+    pub text: String,
+    pub map: SourceMap,
+}
+
+pub struct PreprocessError{
+    location: String,
+    message: String,
+}
+
+//Runs ahead of the lexer: evaluates {$DEFINE}, {$UNDEF}, {$IFDEF}, {$IFNDEF},
+//{$ELSE}, {$ENDIF} and splices in {$I file} / {$INCLUDE file}. The (*$...*)
+//spelling works too. Every other comment, including switch directives like
+//{$R+}, is passed through.
+pub struct Preprocessor{
+    defines: HashSet<String>,
+    include_paths: Vec<PathBuf>,
+    //must match the lexer so both agree where a comment ends
+    nested_comments: bool,
+}
+
+struct Conditional{
+    //whether the enclosing region is copied
+    parent_active: bool,
+    //whether the current branch is copied
+    active: bool,
+    seen_else: bool,
+    location: String,
+}
+
+struct Output{
+    text: Vec<char>,
+    map: SourceMap,
+}
+
+impl fmt::Display for PreprocessError{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result{
+        write!(f, "{}: preprocessor error: {}", self.location, self.message)
+    }
+}
+
+impl SourceMap{
+    //map of a text that was not preprocessed
+    pub fn single(name: &str, text: &str) -> Self{
+        let text: Vec<char> = text.chars().collect();
+        let len = text.len();
+        SourceMap{files: vec![SourceFile{name: name.to_string(), text}], segments: vec![Segment{out_start: 0, file: 0, file_start: 0, len}]}
+    }
+
+    //file name, 1-based line and column of a char offset in the preprocessed text
+    pub fn locate(&self, offset: usize) -> (&str, usize, usize){
+        let (file, pos) = self.position(offset);
+        let (line, col) = line_col(&file.text, pos);
+        (&file.name, line, col)
+    }
+
+    //the whole source line a char offset of the preprocessed text is on
+    pub fn line(&self, offset: usize) -> String{
+        let (file, pos) = self.position(offset);
+        let start = file.text[..pos].iter().rposition(|c| *c == '\n').map_or(0, |i| i + 1);
+        file.text[start..].iter().take_while(|c| **c != '\n' && **c != '\r').collect()
+    }
+
+    fn position(&self, offset: usize) -> (&SourceFile, usize){
+        match self.segments.iter().rposition(|s| s.out_start <= offset){
+            Some(idx) => {
+                let segment = &self.segments[idx];
+                (&self.files[segment.file], segment.file_start + (offset - segment.out_start).min(segment.len))
+            },
+            None => (&self.files[0], 0),
+        }
+    }
+
+    //`file:line:col` of the start of a span
+    pub fn describe(&self, span: Span) -> String{
+        let (file, line, col) = self.locate(span.start);
+        format!("{}:{}:{}", file, line, col)
+    }
+
+    fn add_file(&mut self, name: &str, text: Vec<char>) -> usize{
+        self.files.push(SourceFile{name: name.to_string(), text});
+        self.files.len() - 1
+    }
+
+    fn describe_file(&self, file: usize, pos: usize) -> String{
+        let (line, col) = line_col(&self.files[file].text, pos);
+        format!("{}:{}:{}", self.files[file].name, line, col)
+    }
+}
+
+fn line_col(text: &[char], pos: usize) -> (usize, usize){
+    let mut line = 1;
+    let mut col = 1;
+    for c in text.iter().take(pos){
+        if *c == '\n' {line += 1; col = 1;}
+        else {col += 1;}
+    }
+    (line, col)
+}
+
+impl Output{
+    fn push(&mut self, file: usize, pos: usize, c: char){
+        let out_start = self.text.len();
+        self.text.push(c);
+        if let Some(last) = self.map.segments.last_mut(){
+            if last.file == file && last.file_start + last.len == pos && last.out_start + last.len == out_start{
+                last.len += 1;
+                return;
+            }
+        }
+        self.map.segments.push(Segment{out_start, file, file_start: pos, len: 1});
+    }
+}
+
+impl Default for Preprocessor{
+    fn default() -> Self{
+        Preprocessor::new()
+    }
+}
+
+impl Preprocessor{
+    pub fn new() -> Self{
+        Preprocessor{defines: HashSet::new(), include_paths: Vec::new(), nested_comments: false}
+    }
+
+    pub fn nested_comments(&mut self, on: bool){
+        self.nested_comments = on;
+    }
+
+    //symbol defined before the first line, like `-D NAME` on the command line
+    pub fn define(&mut self, name: &str){
+        self.defines.insert(name.to_ascii_uppercase());
+    }
+
+    //directory searched for include files after the directory of the including file
+    pub fn include_path<P: Into<PathBuf>>(&mut self, dir: P){
+        self.include_paths.push(dir.into());
+    }
+
+    pub fn run(&mut self, name: &str, text: &str) -> Result<Source, PreprocessError>{
+        let mut out = Output{text: Vec::new(), map: SourceMap{files: Vec::new(), segments: Vec::new()}};
+        let file = out.map.add_file(name, text.chars().collect());
+        let mut stack = vec![canonical_path(Path::new(name))];
+        self.process(file, &mut out, &mut stack)?;
+        Ok(Source{text: out.text.into_iter().collect(), map: out.map})
+    }
+
+    fn process(&mut self, file: usize, out: &mut Output, stack: &mut Vec<PathBuf>) -> Result<(), PreprocessError>{
+        let text = out.map.files[file].text.clone();
+        let mut conditionals: Vec<Conditional> = Vec::new();
+        let mut idx = 0;
+
+        while idx < text.len(){
+            let active = conditionals.last().is_none_or(|c| c.active);
+            if comment_start(&text, idx).is_none(){
+                if active {out.push(file, idx, text[idx]);}
+                idx += 1;
+                continue;
+            }
+
+            let start = idx;
+            //an unterminated comment is left for the lexer to report
+            let end = comment_end(&text, start, self.nested_comments).unwrap_or(text.len());
+            idx = end;
+
+            let directive = comment_directive(&text[start..end]).unwrap_or_default();
+            let (name, arg) = split_directive(&directive);
+            let location = if name.is_empty() {String::new()} else {out.map.describe_file(file, start)};
+
+            match name.as_str(){
+                "DEFINE" if active => {self.defines.insert(arg.to_ascii_uppercase());},
+                "UNDEF" if active => {self.defines.remove(&arg.to_ascii_uppercase());},
+                "IFDEF" | "IFNDEF" => {
+                    let defined = self.defines.contains(&arg.to_ascii_uppercase());
+                    let taken = if name == "IFDEF" {defined} else {!defined};
+                    conditionals.push(Conditional{parent_active: active, active: active && taken, seen_else: false, location});
+                },
+                "ELSE" => {
+                    let cond = match conditionals.last_mut(){
+                        Some(c) if !c.seen_else => c,
+                        _ => return Err(PreprocessError{location, message: "{$ELSE} without matching {$IFDEF}".to_string()}),
+                    };
+                    cond.seen_else = true;
+                    cond.active = cond.parent_active && !cond.active;
+                },
+                "ENDIF" => {
+                    if conditionals.pop().is_none(){
+                        return Err(PreprocessError{location, message: "{$ENDIF} without matching {$IFDEF}".to_string()});
+                    }
+                },
+                "I" | "INCLUDE" if active && !arg.is_empty() && !is_switch(&directive) => {
+                    self.include(file, &arg, location, out, stack)?;
+                },
+                "DEFINE" | "UNDEF" | "I" | "INCLUDE" if !active => {},
+                _ => {
+                    if active{
+                        for (pos, c) in text.iter().enumerate().take(end).skip(start){
+                            out.push(file, pos, *c);
+                        }
+                    }
+                },
+            }
+        }
+
+        match conditionals.pop(){
+            Some(c) => Err(PreprocessError{location: c.location, message: "{$IFDEF} is never closed by {$ENDIF}".to_string()}),
+            None => Ok(()),
+        }
+    }
+
+    fn include(&mut self, from: usize, name: &str, location: String, out: &mut Output, stack: &mut Vec<PathBuf>) -> Result<(), PreprocessError>{
+        let path = match self.find_include(&out.map.files[from].name, name){
+            Some(path) => path,
+            None => return Err(PreprocessError{location, message: format!("include file `{}` not found", name)}),
+        };
+        let shown = path.to_string_lossy().into_owned();
+        let key = canonical_path(&path);
+        if stack.contains(&key){
+            return Err(PreprocessError{location, message: format!("include file `{}` includes itself", name)});
+        }
+        if stack.len() >= MAX_INCLUDE_DEPTH{
+            return Err(PreprocessError{location, message: format!("includes nested deeper than {} levels", MAX_INCLUDE_DEPTH)});
+        }
+        let text = match fs::read_to_string(&path){
+            Ok(text) => text,
+            Err(e) => return Err(PreprocessError{location, message: format!("can not read `{}`: {}", shown, e)}),
+        };
+
+        let file = out.map.add_file(&shown, text.chars().collect());
+        stack.push(key);
+        self.process(file, out, stack)?;
+        stack.pop();
+        Ok(())
+    }
+
+    fn find_include(&self, from: &str, name: &str) -> Option<PathBuf>{
+        let base = Path::new(from).parent().map(|p| p.to_path_buf()).unwrap_or_default();
+        std::iter::once(base).chain(self.include_paths.iter().cloned())
+            .map(|dir| dir.join(name))
+            .find(|path| path.is_file())
+    }
+}
+
+//whether a `{$...}` body is a conditional or an include, which only the
+//preprocessor understands
+pub fn is_conditional_or_include(directive: &str) -> bool{
+    let (name, arg) = split_directive(directive);
+    match name.as_str(){
+        "IFDEF" | "IFNDEF" | "ELSE" | "ENDIF" => true,
+        "I" | "INCLUDE" => !arg.is_empty() && !is_switch(directive),
+        _ => false,
+    }
+}
+
+//the same file spelled `a.pas` or `./a.pas` gives the same path
+fn canonical_path(path: &Path) -> PathBuf{
+    fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+//`IFDEF DEBUG` -> ("IFDEF", "DEBUG")
+fn split_directive(directive: &str) -> (String, String){
+    let directive = directive.trim();
+    let name_len = directive.find(|c: char| !c.is_ascii_alphabetic()).unwrap_or(directive.len());
+    let (name, arg) = directive.split_at(name_len);
+    (name.to_ascii_uppercase(), arg.trim().trim_matches('\'').to_string())
+}
+
+//{$I+} and {$I-} switch I/O checking rather than including a file
+fn is_switch(directive: &str) -> bool{
+    let rest = directive.trim()[1..].trim_start();
+    rest.starts_with('+') || rest.starts_with('-')
+}
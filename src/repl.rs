@@ -0,0 +1,154 @@
+use super::lexer::{canonical, Lexer, Token};
+use super::preprocess::SourceMap;
+use super::typecheck::{TypeChecker, TypeError};
+use super::{Interpreter, ParseError, TreeNode, Visit};
+
+const HELP: &str = "\
+VAR a, b : INTEGER     declare variables
+a := 1; b := a * 2     run statements, BEGIN ... END may span lines
+a + b                  print the value of an expression
+:ast <input>           print the syntax tree of the input
+:tokens <input>        print the tokens of the input
+:vars                  print every variable
+:reset                 forget every variable
+:quit                  leave";
+
+//One session of the read-eval-print loop. Declarations, statements and
+//expressions go through the same parser, type checker and `Visit` as a whole
+//program, with variables kept between inputs.
+pub struct Repl{
+    checker: TypeChecker,
+    visit: Visit,
+}
+
+enum Input{
+    Declarations,
+    Statements,
+    Expression,
+}
+
+impl Default for Repl{
+    fn default() -> Self{
+        Repl::new()
+    }
+}
+
+impl Repl{
+    pub fn new() -> Self{
+        Repl{checker: TypeChecker::new(), visit: Visit::new()}
+    }
+
+    //Run one complete input and return what should be printed. Errors are
+    //rendered against the input, as `<repl>:line:col: ...`.
+    pub fn eval(&mut self, input: &str) -> Result<String, String>{
+        let input = input.trim();
+        let command = input.split_whitespace().next().unwrap_or("");
+        match command{
+            "" => Ok(String::new()),
+            ":help" => Ok(HELP.to_string()),
+            ":vars" => Ok(self.vars()),
+            ":reset" => {
+                *self = Repl::new();
+                Ok(String::new())
+            },
+            ":ast" => self.ast(input[command.len()..].trim()),
+            ":tokens" => tokens(input[command.len()..].trim()),
+            c if c.starts_with(':') => Err(format!("unknown command `{}`, try :help", c)),
+            _ => self.run(input),
+        }
+    }
+
+    fn run(&mut self, input: &str) -> Result<String, String>{
+        let map = SourceMap::single("<repl>", input);
+        let render_all = |errors: Vec<TypeError>| errors.iter().map(|e| e.render(&map)).collect::<Vec<_>>().join("\n");
+
+        match classify(input){
+            Input::Declarations => {
+                let mut decls = Interpreter::new(Lexer::new(input)).parse_declarations().map_err(|e| e.render(&map))?;
+                self.checker.check_declarations(&mut decls).map_err(render_all)?;
+                for decl in decls.iter(){
+                    self.visit.declare(decl);
+                }
+                Ok(String::new())
+            },
+            Input::Statements => {
+                let mut node = Interpreter::new(Lexer::new(input)).parse_statements().map_err(|e| e.render(&map))?;
+                self.checker.check_statement(&mut node).map_err(render_all)?;
+                self.visit.execute(&node).map_err(|e| e.render(&map))?;
+                Ok(String::new())
+            },
+            Input::Expression => {
+                let input = input.strip_suffix(';').unwrap_or(input);
+                let mut node = Interpreter::new(Lexer::new(input)).parse_expression().map_err(|e| e.render(&map))?;
+                self.checker.check_expression(&mut node).map_err(render_all)?;
+                let value = self.visit.evaluate(&node).map_err(|e| e.render(&map))?;
+                Ok(format!("{:?}", value))
+            },
+        }
+    }
+
+    fn vars(&self) -> String{
+        self.visit.var_names.iter().map(|name| match &self.visit.var_table[&canonical(name)]{
+            Some(val) => format!("{}: {:?}", name, val),
+            None => format!("{}: <not initialized>", name),
+        }).collect::<Vec<_>>().join("\n")
+    }
+
+    fn ast(&self, input: &str) -> Result<String, String>{
+        let map = SourceMap::single("<repl>", input);
+        let mut parser = Interpreter::new(Lexer::new(input));
+        let nodes = match classify(input){
+            Input::Declarations => parser.parse_declarations(),
+            Input::Statements => parser.parse_statements().map(|n| vec![n]),
+            Input::Expression => parser.parse_expression().map(|n| vec![n]),
+        };
+        let nodes: Vec<TreeNode> = nodes.map_err(|e| e.render(&map))?;
+        Ok(nodes.iter().map(|n| n.to_string()).collect::<String>().trim_end().to_string())
+    }
+}
+
+//false while a BEGIN is still open or a comment is unterminated, the loop
+//then reads another line before calling `eval`
+pub fn is_complete(input: &str) -> bool{
+    if input.trim_start().starts_with(':'){
+        return true;
+    }
+    let mut depth = 0;
+    for token in Lexer::new(input){
+        match token{
+            Ok(t) if t.token == Token::KEYWORD("BEGIN".to_string()) => depth += 1,
+            Ok(t) if t.token == Token::KEYWORD("END".to_string()) => depth -= 1,
+            Ok(_) => {},
+            Err(e) => return e.message != "unterminated comment",
+        }
+    }
+    depth <= 0
+}
+
+fn classify(input: &str) -> Input{
+    let mut lexer = Lexer::new(input);
+    let first = lexer.peek().and_then(|t| t.as_ref().ok()).map(|t| t.token.clone());
+    let second = lexer.peek_nth(1).and_then(|t| t.as_ref().ok()).map(|t| t.token.clone());
+    match (first, second){
+        (Some(Token::KEYWORD(s)), _) if s == "VAR" => Input::Declarations,
+        (Some(Token::KEYWORD(s)), _) if s == "BEGIN" => Input::Statements,
+        (Some(Token::ID(_)), Some(Token::ASSIGN)) => Input::Statements,
+        (Some(Token::SEMI), _) => Input::Statements,
+        _ => Input::Expression,
+    }
+}
+
+fn tokens(input: &str) -> Result<String, String>{
+    let map = SourceMap::single("<repl>", input);
+    let mut lines = Vec::new();
+    for token in Lexer::new(input){
+        match token{
+            Ok(t) => {
+                let (_, line, col) = map.locate(t.span.start);
+                lines.push(format!("{}:{}: {:?}", line, col, t.token));
+            },
+            Err(e) => return Err(ParseError::from(e).render(&map)),
+        }
+    }
+    Ok(lines.join("\n"))
+}
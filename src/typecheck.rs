@@ -0,0 +1,282 @@
+use std::fmt;
+use std::convert::TryFrom;
+use std::collections::HashMap;
+
+use super::{canonical, Span, Token, TreeNode};
+use super::preprocess::SourceMap;
+use super::visitor::VisitorMut;
+
+//static type of a variable or expression
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Type{
+    Integer,
+    Real,
+    //INTEGER restricted to low..high, both bounds inclusive
+    Subrange(i64, i64),
+}
+
+impl Type{
+    //the type a value of this type has inside an expression
+    pub fn base(self) -> Type{
+        match self{
+            Type::Subrange(_, _) => Type::Integer,
+            t => t,
+        }
+    }
+}
+
+impl fmt::Display for Type{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result{
+        match self{
+            Type::Integer => write!(f, "INTEGER"),
+            Type::Real => write!(f, "REAL"),
+            Type::Subrange(low, high) => write!(f, "{}..{}", low, high),
+        }
+    }
+}
+
+pub struct TypeError{
+    pub span: Span,
+    pub message: String,
+}
+
+impl TypeError{
+    fn new(span: Span, message: String) -> Self{
+        TypeError{span, message}
+    }
+
+    pub fn render(&self, map: &SourceMap) -> String{
+        format!("{}: type error: {}", map.describe(self.span), self.message)
+    }
+}
+
+//Walks a parsed program before execution, computes the static type of every
+//expression node and stores it in `TreeNode::ty`. Assignment targets are
+//annotated with the declared type of the variable.
+pub struct TypeChecker{
+    //keyed by canonical name
+    symbols: HashMap<String, Type>,
+    errors: Vec<TypeError>,
+}
+
+impl Default for TypeChecker{
+    fn default() -> Self{
+        TypeChecker::new()
+    }
+}
+
+impl TypeChecker{
+    pub fn new() -> Self{
+        TypeChecker{symbols: HashMap::new(), errors: Vec::new()}
+    }
+
+    // PROGRAM BLOCK VARDEC Empty COMP
+    pub fn check(&mut self, root: &mut TreeNode) -> Result<(), Vec<TypeError>>{
+        if root.node != Token::ASTNode("PROGRAM".to_string()){
+            panic!("error in fn check, root is not a program");
+        }
+        self.record(|c| c.visit_program(root));
+        self.finish()
+    }
+
+    //declarations outside a program, for the REPL. Nothing is declared if one of them is rejected.
+    pub fn check_declarations(&mut self, decls: &mut [TreeNode]) -> Result<(), Vec<TypeError>>{
+        let symbols = self.symbols.clone();
+        for node in decls.iter_mut(){
+            self.record(|c| c.visit_var_decl(node));
+        }
+        if !self.errors.is_empty(){
+            self.symbols = symbols;
+        }
+        self.finish()
+    }
+
+    //an ASSIGN, COMP or Empty statement using the variables declared so far
+    pub fn check_statement(&mut self, root: &mut TreeNode) -> Result<(), Vec<TypeError>>{
+        self.record(|c| c.visit_node(root));
+        self.finish()
+    }
+
+    pub fn check_expression(&mut self, root: &mut TreeNode) -> Result<Type, Vec<TypeError>>{
+        self.expr(root).map_err(|e| vec![e])
+    }
+
+    fn finish(&mut self) -> Result<(), Vec<TypeError>>{
+        if self.errors.is_empty(){
+            Ok(())
+        }else{
+            Err(std::mem::take(&mut self.errors))
+        }
+    }
+
+    //a declaration or statement stops at its first error, the next one is still checked
+    fn record(&mut self, check: impl FnOnce(&mut Self) -> Result<Option<Type>, TypeError>){
+        if let Err(e) = check(self){
+            self.errors.push(e);
+        }
+    }
+
+    fn expr(&mut self, root: &mut TreeNode) -> Result<Type, TypeError>{
+        Ok(self.visit_node(root)?.expect("error in fn expr, expression without a type"))
+    }
+
+    //both operands of a binary operator
+    fn operands(&mut self, root: &mut TreeNode) -> Result<(Type, Type), TypeError>{
+        let left = self.expr(&mut root.sub_nodes[0])?;
+        let right = self.expr(&mut root.sub_nodes[1])?;
+        Ok((left, right))
+    }
+}
+
+//Declarations and statements return None, expressions and type specs their
+//type. Every expression node is annotated on the way.
+impl VisitorMut for TypeChecker{
+    type Output = Option<Type>;
+    type Error = TypeError;
+
+    fn visit_block(&mut self, root: &mut TreeNode) -> Result<Option<Type>, TypeError>{
+        for node in root.sub_nodes.iter_mut(){
+            self.record(|c| c.visit_node(node));
+        }
+        Ok(None)
+    }
+
+    fn visit_compound(&mut self, root: &mut TreeNode) -> Result<Option<Type>, TypeError>{
+        self.visit_block(root)
+    }
+
+    fn visit_var_decl(&mut self, root: &mut TreeNode) -> Result<Option<Type>, TypeError>{
+        let var_name = match &root.sub_nodes[0].node{
+            Token::ID(s) => s.clone(),
+            _ => panic!("error in fn visit_var_decl, wrong var_name: {:?}", root.sub_nodes[0].node),
+        };
+        let var_type = self.visit_type(&mut root.sub_nodes[1])?.expect("error in fn visit_var_decl, type without a type");
+
+        if self.symbols.contains_key(&canonical(&var_name)){
+            return Err(TypeError::new(root.sub_nodes[0].span, format!("variable `{}` has already been declared", var_name)));
+        }
+        root.sub_nodes[0].ty = Some(var_type);
+        self.symbols.insert(canonical(&var_name), var_type);
+        Ok(None)
+    }
+
+    //subrange bounds are constants, they are not visited
+    fn visit_type(&mut self, root: &mut TreeNode) -> Result<Option<Type>, TypeError>{
+        let ty = match &root.node{
+            Token::KEYWORD(s) if s == "INTEGER" => Type::Integer,
+            Token::KEYWORD(s) if s == "REAL" => Type::Real,
+            Token::ASTNode(s) if s == "SUBRANGE" => {
+                let low = constant_value(&root.sub_nodes[0]);
+                let high = constant_value(&root.sub_nodes[1]);
+                match (low, high){
+                    (Some(low), Some(high)) if low <= high => Type::Subrange(low, high),
+                    (Some(low), Some(high)) => return Err(TypeError::new(root.span, format!("empty subrange {}..{}", low, high))),
+                    _ => return Err(TypeError::new(root.span, "subrange bound does not fit in INTEGER".to_string())),
+                }
+            },
+            _ => panic!("error in fn visit_type, wrong var_type: {:?}", root.node),
+        };
+        Ok(Some(ty))
+    }
+
+    fn visit_assign(&mut self, root: &mut TreeNode) -> Result<Option<Type>, TypeError>{
+        let target = self.expr(&mut root.sub_nodes[0])?;
+        let value = self.expr(&mut root.sub_nodes[1])?;
+
+        let name = match &root.sub_nodes[0].node{
+            Token::ID(s) => s.clone(),
+            _ => String::new(),
+        };
+        if let Err(reason) = assignment_compatible(target, value){
+            return Err(TypeError::new(root.span, format!("cannot assign to variable `{}`: {}", name, reason)));
+        }
+
+        //a constant outside the subrange can never be assigned
+        if let (Type::Subrange(low, high), Some(n)) = (target, constant_value(&root.sub_nodes[1])){
+            if n < low || n > high{
+                return Err(TypeError::new(root.sub_nodes[1].span, format!("constant {} is out of range {}..{} of variable `{}`", n, low, high, name)));
+            }
+        }
+        Ok(None)
+    }
+
+    fn visit_binary_op(&mut self, root: &mut TreeNode) -> Result<Option<Type>, TypeError>{
+        let (left, right) = self.operands(root)?;
+        let ty = match &root.node{
+            Token::KEYWORD(s) if s == "DIV" || s == "MOD" => {
+                for (t, operand) in [left, right].iter().zip(root.sub_nodes.iter()){
+                    if t.base() != Type::Integer{
+                        return Err(TypeError::new(operand.span, format!("{} requires INTEGER operands, found {}", s, t)));
+                    }
+                }
+                Type::Integer
+            },
+            //`/` always yields REAL, even for two INTEGER operands
+            Token::OP2('/') => Type::Real,
+            _ => match (left.base(), right.base()){
+                (Type::Integer, Type::Integer) => Type::Integer,
+                _ => Type::Real,
+            },
+        };
+        root.ty = Some(ty);
+        Ok(Some(ty))
+    }
+
+    fn visit_unary_op(&mut self, root: &mut TreeNode) -> Result<Option<Type>, TypeError>{
+        let ty = self.expr(&mut root.sub_nodes[0])?.base();
+        root.ty = Some(ty);
+        Ok(Some(ty))
+    }
+
+    fn visit_var(&mut self, root: &mut TreeNode) -> Result<Option<Type>, TypeError>{
+        let ty = match &root.node{
+            Token::ID(s) => match self.symbols.get(&canonical(s)){
+                Some(t) => *t,
+                None => return Err(TypeError::new(root.span, format!("variable `{}` has not been declared", s))),
+            },
+            _ => panic!("error in fn visit_var, wrong node: {:?}", root.node),
+        };
+        root.ty = Some(ty);
+        Ok(Some(ty))
+    }
+
+    fn visit_num(&mut self, root: &mut TreeNode) -> Result<Option<Type>, TypeError>{
+        let ty = match &root.node{
+            Token::INTEGER_CONST(_) => Type::Integer,
+            _ => Type::Real,
+        };
+        root.ty = Some(ty);
+        Ok(Some(ty))
+    }
+}
+
+//ISO 7185 6.4.6: a value of type `source` may be assigned to a variable (or passed
+//as a value parameter, or returned as a function result) of type `target`.
+//Subrange values are checked against the target bounds at run time.
+pub fn assignment_compatible(target: Type, source: Type) -> Result<(), String>{
+    match (target, source){
+        (Type::Real, Type::Real) => Ok(()),
+        (Type::Real, Type::Integer) | (Type::Real, Type::Subrange(_, _)) => Ok(()),
+        (Type::Integer, Type::Integer) | (Type::Integer, Type::Subrange(_, _)) => Ok(()),
+        (Type::Subrange(_, _), Type::Integer) => Ok(()),
+        (Type::Subrange(low, high), Type::Subrange(s_low, s_high)) => {
+            if s_high < low || s_low > high{
+                Err(format!("subrange {}..{} has no value in common with {}..{}", s_low, s_high, low, high))
+            }else{
+                Ok(())
+            }
+        },
+        (Type::Integer, Type::Real) | (Type::Subrange(_, _), Type::Real) =>
+            Err(format!("REAL is not assignment-compatible with {}, real values are never implicitly truncated", target)),
+    }
+}
+
+//value of an integer constant, optionally signed
+fn constant_value(root: &TreeNode) -> Option<i64>{
+    match &root.node{
+        Token::INTEGER_CONST(n) => i64::try_from(*n).ok(),
+        Token::UNARY('-') => constant_value(&root.sub_nodes[0]).map(|n| -n),
+        Token::UNARY('+') => constant_value(&root.sub_nodes[0]),
+        _ => None,
+    }
+}
@@ -0,0 +1,219 @@
+use super::lexer::Token;
+use super::TreeNode;
+
+//A pass over the AST overrides the `visit_*` methods of the node kinds it
+//cares about. Each `visit_*` defaults to the matching `walk_*`, which visits
+//the children; an override calls `self.walk_*` itself to keep descending.
+//Every visit returns `Result<Self::Output, Self::Error>`: a type checker or
+//code generator returns a value for each expression and stops at the first
+//error with `?`. The walks drop the values of the children and return
+//`Output::default()`, and leaves return it without visiting anything.
+//
+//  PROGRAM   visit_program    [ID name, BLOCK]
+//  BLOCK     visit_block      [VARDEC..., COMP]
+//  VARDEC    visit_var_decl   [ID, type]
+//  type      visit_type       KEYWORD INTEGER/REAL, or SUBRANGE [low, high]
+//  COMP      visit_compound   [statement...]
+//  ASSIGN    visit_assign     [ID, expr]
+//  Empty     visit_empty
+//  OP1 OP2 DIV MOD visit_binary_op [expr, expr]
+//  UNARY     visit_unary_op   [expr]
+//  ID        visit_var
+//  INTEGER_CONST REAL_CONST visit_num
+pub trait Visitor{
+    type Output: Default;
+    type Error;
+
+    //dispatch on the kind of any node
+    fn visit_node(&mut self, node: &TreeNode) -> Result<Self::Output, Self::Error>{
+        match node_kind(node){
+            Kind::Program => self.visit_program(node),
+            Kind::Block => self.visit_block(node),
+            Kind::VarDecl => self.visit_var_decl(node),
+            Kind::Type => self.visit_type(node),
+            Kind::Compound => self.visit_compound(node),
+            Kind::Assign => self.visit_assign(node),
+            Kind::Empty => self.visit_empty(node),
+            Kind::BinaryOp => self.visit_binary_op(node),
+            Kind::UnaryOp => self.visit_unary_op(node),
+            Kind::Var => self.visit_var(node),
+            Kind::Num => self.visit_num(node),
+        }
+    }
+
+    fn visit_program(&mut self, node: &TreeNode) -> Result<Self::Output, Self::Error>{self.walk_program(node)}
+    fn visit_block(&mut self, node: &TreeNode) -> Result<Self::Output, Self::Error>{self.walk_block(node)}
+    fn visit_var_decl(&mut self, node: &TreeNode) -> Result<Self::Output, Self::Error>{self.walk_var_decl(node)}
+    fn visit_type(&mut self, node: &TreeNode) -> Result<Self::Output, Self::Error>{self.walk_type(node)}
+    fn visit_compound(&mut self, node: &TreeNode) -> Result<Self::Output, Self::Error>{self.walk_compound(node)}
+    fn visit_assign(&mut self, node: &TreeNode) -> Result<Self::Output, Self::Error>{self.walk_assign(node)}
+    fn visit_empty(&mut self, _node: &TreeNode) -> Result<Self::Output, Self::Error>{Ok(Self::Output::default())}
+    fn visit_binary_op(&mut self, node: &TreeNode) -> Result<Self::Output, Self::Error>{self.walk_binary_op(node)}
+    fn visit_unary_op(&mut self, node: &TreeNode) -> Result<Self::Output, Self::Error>{self.walk_unary_op(node)}
+    fn visit_var(&mut self, _node: &TreeNode) -> Result<Self::Output, Self::Error>{Ok(Self::Output::default())}
+    fn visit_num(&mut self, _node: &TreeNode) -> Result<Self::Output, Self::Error>{Ok(Self::Output::default())}
+
+    //the program name is not a variable and is not visited
+    fn walk_program(&mut self, node: &TreeNode) -> Result<Self::Output, Self::Error>{
+        self.visit_block(&node.sub_nodes[1])
+    }
+
+    fn walk_block(&mut self, node: &TreeNode) -> Result<Self::Output, Self::Error>{
+        for n in node.sub_nodes.iter(){
+            self.visit_node(n)?;
+        }
+        Ok(Self::Output::default())
+    }
+
+    fn walk_var_decl(&mut self, node: &TreeNode) -> Result<Self::Output, Self::Error>{
+        self.visit_var(&node.sub_nodes[0])?;
+        self.visit_type(&node.sub_nodes[1])?;
+        Ok(Self::Output::default())
+    }
+
+    fn walk_type(&mut self, node: &TreeNode) -> Result<Self::Output, Self::Error>{
+        for n in node.sub_nodes.iter(){
+            self.visit_node(n)?;
+        }
+        Ok(Self::Output::default())
+    }
+
+    fn walk_compound(&mut self, node: &TreeNode) -> Result<Self::Output, Self::Error>{
+        for n in node.sub_nodes.iter(){
+            self.visit_node(n)?;
+        }
+        Ok(Self::Output::default())
+    }
+
+    fn walk_assign(&mut self, node: &TreeNode) -> Result<Self::Output, Self::Error>{
+        self.visit_var(&node.sub_nodes[0])?;
+        self.visit_node(&node.sub_nodes[1])?;
+        Ok(Self::Output::default())
+    }
+
+    fn walk_binary_op(&mut self, node: &TreeNode) -> Result<Self::Output, Self::Error>{
+        self.visit_node(&node.sub_nodes[0])?;
+        self.visit_node(&node.sub_nodes[1])?;
+        Ok(Self::Output::default())
+    }
+
+    fn walk_unary_op(&mut self, node: &TreeNode) -> Result<Self::Output, Self::Error>{
+        self.visit_node(&node.sub_nodes[0])?;
+        Ok(Self::Output::default())
+    }
+}
+
+//Same walk as `Visitor` with mutable access, for passes that rewrite the tree
+//in place. An override may replace `*node` entirely.
+pub trait VisitorMut{
+    type Output: Default;
+    type Error;
+
+    fn visit_node(&mut self, node: &mut TreeNode) -> Result<Self::Output, Self::Error>{
+        match node_kind(node){
+            Kind::Program => self.visit_program(node),
+            Kind::Block => self.visit_block(node),
+            Kind::VarDecl => self.visit_var_decl(node),
+            Kind::Type => self.visit_type(node),
+            Kind::Compound => self.visit_compound(node),
+            Kind::Assign => self.visit_assign(node),
+            Kind::Empty => self.visit_empty(node),
+            Kind::BinaryOp => self.visit_binary_op(node),
+            Kind::UnaryOp => self.visit_unary_op(node),
+            Kind::Var => self.visit_var(node),
+            Kind::Num => self.visit_num(node),
+        }
+    }
+
+    fn visit_program(&mut self, node: &mut TreeNode) -> Result<Self::Output, Self::Error>{self.walk_program(node)}
+    fn visit_block(&mut self, node: &mut TreeNode) -> Result<Self::Output, Self::Error>{self.walk_block(node)}
+    fn visit_var_decl(&mut self, node: &mut TreeNode) -> Result<Self::Output, Self::Error>{self.walk_var_decl(node)}
+    fn visit_type(&mut self, node: &mut TreeNode) -> Result<Self::Output, Self::Error>{self.walk_type(node)}
+    fn visit_compound(&mut self, node: &mut TreeNode) -> Result<Self::Output, Self::Error>{self.walk_compound(node)}
+    fn visit_assign(&mut self, node: &mut TreeNode) -> Result<Self::Output, Self::Error>{self.walk_assign(node)}
+    fn visit_empty(&mut self, _node: &mut TreeNode) -> Result<Self::Output, Self::Error>{Ok(Self::Output::default())}
+    fn visit_binary_op(&mut self, node: &mut TreeNode) -> Result<Self::Output, Self::Error>{self.walk_binary_op(node)}
+    fn visit_unary_op(&mut self, node: &mut TreeNode) -> Result<Self::Output, Self::Error>{self.walk_unary_op(node)}
+    fn visit_var(&mut self, _node: &mut TreeNode) -> Result<Self::Output, Self::Error>{Ok(Self::Output::default())}
+    fn visit_num(&mut self, _node: &mut TreeNode) -> Result<Self::Output, Self::Error>{Ok(Self::Output::default())}
+
+    fn walk_program(&mut self, node: &mut TreeNode) -> Result<Self::Output, Self::Error>{
+        self.visit_block(&mut node.sub_nodes[1])
+    }
+
+    fn walk_block(&mut self, node: &mut TreeNode) -> Result<Self::Output, Self::Error>{
+        for n in node.sub_nodes.iter_mut(){
+            self.visit_node(n)?;
+        }
+        Ok(Self::Output::default())
+    }
+
+    fn walk_var_decl(&mut self, node: &mut TreeNode) -> Result<Self::Output, Self::Error>{
+        self.visit_var(&mut node.sub_nodes[0])?;
+        self.visit_type(&mut node.sub_nodes[1])?;
+        Ok(Self::Output::default())
+    }
+
+    fn walk_type(&mut self, node: &mut TreeNode) -> Result<Self::Output, Self::Error>{
+        for n in node.sub_nodes.iter_mut(){
+            self.visit_node(n)?;
+        }
+        Ok(Self::Output::default())
+    }
+
+    fn walk_compound(&mut self, node: &mut TreeNode) -> Result<Self::Output, Self::Error>{
+        for n in node.sub_nodes.iter_mut(){
+            self.visit_node(n)?;
+        }
+        Ok(Self::Output::default())
+    }
+
+    fn walk_assign(&mut self, node: &mut TreeNode) -> Result<Self::Output, Self::Error>{
+        self.visit_var(&mut node.sub_nodes[0])?;
+        self.visit_node(&mut node.sub_nodes[1])?;
+        Ok(Self::Output::default())
+    }
+
+    fn walk_binary_op(&mut self, node: &mut TreeNode) -> Result<Self::Output, Self::Error>{
+        self.visit_node(&mut node.sub_nodes[0])?;
+        self.visit_node(&mut node.sub_nodes[1])?;
+        Ok(Self::Output::default())
+    }
+
+    fn walk_unary_op(&mut self, node: &mut TreeNode) -> Result<Self::Output, Self::Error>{
+        self.visit_node(&mut node.sub_nodes[0])?;
+        Ok(Self::Output::default())
+    }
+}
+
+enum Kind{
+    Program,
+    Block,
+    VarDecl,
+    Type,
+    Compound,
+    Assign,
+    Empty,
+    BinaryOp,
+    UnaryOp,
+    Var,
+    Num,
+}
+
+fn node_kind(node: &TreeNode) -> Kind{
+    match &node.node{
+        Token::ASTNode(s) if s == "PROGRAM" => Kind::Program,
+        Token::ASTNode(s) if s == "BLOCK" => Kind::Block,
+        Token::ASTNode(s) if s == "VARDEC" => Kind::VarDecl,
+        Token::ASTNode(s) if s == "SUBRANGE" => Kind::Type,
+        Token::ASTNode(s) if s == "COMP" => Kind::Compound,
+        Token::ASTNode(s) if s == "Empty" => Kind::Empty,
+        Token::ASSIGN => Kind::Assign,
+        Token::KEYWORD(s) if s == "INTEGER" || s == "REAL" => Kind::Type,
+        Token::OP1(_) | Token::OP2(_) | Token::KEYWORD(_) => Kind::BinaryOp,
+        Token::UNARY(_) => Kind::UnaryOp,
+        Token::ID(_) => Kind::Var,
+        Token::INTEGER_CONST(_) | Token::REAL_CONST(_) => Kind::Num,
+        _ => panic!("error in fn node_kind, wrong node: {:?}", node.node),
+    }
+}
@@ -0,0 +1,402 @@
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::fmt::Write;
+
+use super::lexer::{canonical, Span, Token};
+use super::preprocess::SourceMap;
+use super::typecheck::Type;
+use super::{RuntimeError, TreeNode};
+
+//Strings the runtime functions below refer to as `@name`, stored first in memory.
+const RUNTIME_STRINGS: [(&str, &str); 11] = [
+    ("@runtime_error", ": runtime error: "),
+    ("@newline", "\n"),
+    ("@integer_overflow", "integer overflow"),
+    ("@division_by_zero", "division by zero"),
+    ("@mod_by_negative", "MOD by negative value "),
+    ("@invalid_real", "invalid floating point result "),
+    ("@value", "value "),
+    ("@out_of_range", " out of range "),
+    ("@dots", ".."),
+    ("@of_variable", " of variable `"),
+    ("@backtick_newline", "`\n"),
+];
+
+//Runtime support of every module. The checks mirror `Visit`: same messages at
+//the same positions, written to fd 2 before the module traps with `unreachable`.
+//Strings are passed as an offset and a length into memory.
+const RUNTIME: &str = r#"
+  (func $error (param $loc i32) (param $loc_len i32)
+    i32.const 2 local.get $loc local.get $loc_len call $write
+    i32.const 2 @runtime_error call $write)
+
+  (func $fail (param $loc i32) (param $loc_len i32) (param $msg i32) (param $msg_len i32)
+    local.get $loc local.get $loc_len call $error
+    i32.const 2 local.get $msg local.get $msg_len call $write
+    i32.const 2 @newline call $write
+    unreachable)
+
+  (func $add_int (param $a i64) (param $b i64) (param $checked i32) (param $loc i32) (param $loc_len i32) (result i64)
+    (local $r i64)
+    local.get $a local.get $b i64.add local.set $r
+    local.get $checked
+    if
+      local.get $a local.get $r i64.xor
+      local.get $b local.get $r i64.xor
+      i64.and i64.const 0 i64.lt_s
+      if
+        local.get $loc local.get $loc_len @integer_overflow call $fail
+      end
+    end
+    local.get $r)
+
+  (func $sub_int (param $a i64) (param $b i64) (param $checked i32) (param $loc i32) (param $loc_len i32) (result i64)
+    (local $r i64)
+    local.get $a local.get $b i64.sub local.set $r
+    local.get $checked
+    if
+      local.get $a local.get $b i64.xor
+      local.get $a local.get $r i64.xor
+      i64.and i64.const 0 i64.lt_s
+      if
+        local.get $loc local.get $loc_len @integer_overflow call $fail
+      end
+    end
+    local.get $r)
+
+  (func $mul_int (param $a i64) (param $b i64) (param $checked i32) (param $loc i32) (param $loc_len i32) (result i64)
+    (local $r i64)
+    local.get $a local.get $b i64.mul local.set $r
+    local.get $checked
+    if
+      local.get $a i64.const -1 i64.eq
+      if (result i32)
+        local.get $b i64.const -9223372036854775808 i64.eq
+      else
+        local.get $a i64.eqz
+        if (result i32)
+          i32.const 0
+        else
+          local.get $r local.get $a i64.div_s local.get $b i64.ne
+        end
+      end
+      if
+        local.get $loc local.get $loc_len @integer_overflow call $fail
+      end
+    end
+    local.get $r)
+
+  (func $div_int (param $a i64) (param $b i64) (param $checked i32) (param $divisor i32) (param $divisor_len i32) (param $loc i32) (param $loc_len i32) (result i64)
+    local.get $b i64.eqz
+    if
+      local.get $divisor local.get $divisor_len @division_by_zero call $fail
+    end
+    local.get $a i64.const -9223372036854775808 i64.eq
+    local.get $b i64.const -1 i64.eq
+    i32.and
+    if
+      local.get $checked
+      if
+        local.get $loc local.get $loc_len @integer_overflow call $fail
+      end
+      local.get $a return
+    end
+    local.get $a local.get $b i64.div_s)
+
+  (func $mod_int (param $a i64) (param $b i64) (param $divisor i32) (param $divisor_len i32) (result i64)
+    (local $r i64)
+    local.get $b i64.eqz
+    if
+      local.get $divisor local.get $divisor_len @division_by_zero call $fail
+    end
+    local.get $b i64.const 0 i64.lt_s
+    if
+      local.get $divisor local.get $divisor_len call $error
+      i32.const 2 @mod_by_negative call $write
+      i32.const 2 local.get $b call $write_i64
+      i32.const 2 @newline call $write
+      unreachable
+    end
+    local.get $a local.get $b i64.rem_s local.set $r
+    local.get $r i64.const 0 i64.lt_s
+    if (result i64)
+      local.get $r local.get $b i64.add
+    else
+      local.get $r
+    end)
+
+  (func $neg_int (param $a i64) (param $checked i32) (param $loc i32) (param $loc_len i32) (result i64)
+    local.get $a i64.const -9223372036854775808 i64.eq
+    if
+      local.get $checked
+      if
+        local.get $loc local.get $loc_len @integer_overflow call $fail
+      end
+      local.get $a return
+    end
+    i64.const 0 local.get $a i64.sub)
+
+  (func $real_result (param $x f64) (param $loc i32) (param $loc_len i32) (result f64)
+    local.get $x local.get $x f64.sub f64.const 0 f64.ne
+    if
+      local.get $loc local.get $loc_len call $error
+      i32.const 2 @invalid_real call $write
+      i32.const 2 local.get $x call $write_f64
+      i32.const 2 @newline call $write
+      unreachable
+    end
+    local.get $x)
+
+  (func $div_real (param $a f64) (param $b f64) (param $divisor i32) (param $divisor_len i32) (param $loc i32) (param $loc_len i32) (result f64)
+    local.get $b f64.const 0 f64.eq
+    if
+      local.get $divisor local.get $divisor_len @division_by_zero call $fail
+    end
+    local.get $a local.get $b f64.div local.get $loc local.get $loc_len call $real_result)
+
+  (func $range (param $n i64) (param $low i64) (param $high i64) (param $loc i32) (param $loc_len i32) (param $name i32) (param $name_len i32) (result i64)
+    local.get $n local.get $low i64.lt_s
+    local.get $n local.get $high i64.gt_s
+    i32.or
+    if
+      local.get $loc local.get $loc_len call $error
+      i32.const 2 @value call $write
+      i32.const 2 local.get $n call $write_i64
+      i32.const 2 @out_of_range call $write
+      i32.const 2 local.get $low call $write_i64
+      i32.const 2 @dots call $write
+      i32.const 2 local.get $high call $write_i64
+      i32.const 2 @of_variable call $write
+      i32.const 2 local.get $name local.get $name_len call $write
+      i32.const 2 @backtick_newline call $write
+      unreachable
+    end
+    local.get $n)
+"#;
+
+struct Var{
+    local: String,
+    ty: Type,
+}
+
+//Translates a type checked program to a WebAssembly text module. The program
+//body is the exported function `main`; the host provides
+//  write(fd i32, offset i32, length i32)   write bytes of memory
+//  write_i64(fd i32, n i64)                write n in decimal
+//  write_f64(fd i32, x f64)                write x as Rust's `{}` does: the
+//                                          shortest digits that read back as
+//                                          x, no exponent, inf, -inf, NaN
+//and treats a trap as a runtime error. `main` writes what `run` prints to fd 1.
+struct WatEmitter<'a>{
+    map: &'a SourceMap,
+    //canonical name to variable
+    vars: HashMap<String, Var>,
+    //declared spelling in declaration order
+    names: Vec<String>,
+    body: String,
+    data: Vec<u8>,
+    strings: HashMap<String, (usize, usize)>,
+}
+
+pub fn emit_wat(root: &TreeNode, map: &SourceMap) -> Result<String, RuntimeError>{
+    let mut e = WatEmitter{map, vars: HashMap::new(), names: Vec::new(), body: String::new(), data: Vec::new(), strings: HashMap::new()};
+    let mut runtime = RUNTIME.to_string();
+    for (key, text) in RUNTIME_STRINGS.iter(){
+        let string = e.string(text);
+        runtime = runtime.replace(key, &string);
+    }
+
+    let name = match &root.sub_nodes[0].node{
+        Token::ID(s) => s.clone(),
+        _ => String::new(),
+    };
+    for node in root.sub_nodes[1].sub_nodes.iter(){
+        match &node.node{
+            Token::ASTNode(s) if s == "VARDEC" => e.var_decl(node),
+            Token::ASTNode(s) if s == "COMP" => e.compound(node)?,
+            _ => panic!("error in fn emit_wat, wrong AST node: {:?}", node.node),
+        }
+    }
+    for name in e.names.clone().iter(){
+        let var = &e.vars[&canonical(name)];
+        let (local, real) = (var.local.clone(), var.ty == Type::Real);
+        let unset = e.string(&format!("{}: <not initialized>\n", name));
+        let prefix = e.string(&format!("{}: {}(", name, if real {"REAL"} else {"INTEGER"}));
+        let suffix = e.string(")\n");
+        e.line(&format!("local.get {}_set", local));
+        e.line("if");
+        e.line(&format!("  i32.const 1 {} call $write", prefix));
+        e.line(&format!("  i32.const 1 local.get {} call {}", local, if real {"$write_f64"} else {"$write_i64"}));
+        e.line(&format!("  i32.const 1 {} call $write", suffix));
+        e.line("else");
+        e.line(&format!("  i32.const 1 {} call $write", unset));
+        e.line("end");
+    }
+
+    let mut out = format!("(module ;; program {}\n", name);
+    out += "  (import \"host\" \"write\" (func $write (param i32 i32 i32)))\n";
+    out += "  (import \"host\" \"write_i64\" (func $write_i64 (param i32 i64)))\n";
+    out += "  (import \"host\" \"write_f64\" (func $write_f64 (param i32 f64)))\n";
+    let _ = writeln!(out, "  (memory (export \"memory\") {})", (e.data.len() / 65536 + 1));
+    let _ = writeln!(out, "  (data (i32.const 0) \"{}\")", escape(&e.data));
+    out += &runtime;
+    out += "\n  (func $main (export \"main\")\n";
+    for name in e.names.iter(){
+        let var = &e.vars[&canonical(name)];
+        let ty = if var.ty == Type::Real {"f64"} else {"i64"};
+        let _ = writeln!(out, "    (local {} {}) (local {}_set i32)", var.local, ty, var.local);
+    }
+    out += &e.body;
+    out.truncate(out.trim_end().len());
+    out += ")\n)\n";
+    Ok(out)
+}
+
+impl WatEmitter<'_>{
+    fn line(&mut self, text: &str){
+        let _ = writeln!(self.body, "    {}", text);
+    }
+
+    //`i32.const offset i32.const length` of a string in memory, each string
+    //is stored once
+    fn string(&mut self, s: &str) -> String{
+        let data = &mut self.data;
+        let (offset, len) = *self.strings.entry(s.to_string()).or_insert_with(|| {
+            data.extend_from_slice(s.as_bytes());
+            (data.len() - s.len(), s.len())
+        });
+        format!("i32.const {} i32.const {}", offset, len)
+    }
+
+    fn location(&mut self, span: Span) -> String{
+        let location = self.map.describe(span);
+        self.string(&location)
+    }
+
+    fn var_decl(&mut self, root: &TreeNode){
+        if let (Token::ID(s), Some(ty)) = (&root.sub_nodes[0].node, root.sub_nodes[0].ty){
+            let local = format!("$v_{}", canonical(s));
+            self.vars.insert(canonical(s), Var{local, ty});
+            self.names.push(s.clone());
+        }
+    }
+
+    fn compound(&mut self, root: &TreeNode) -> Result<(), RuntimeError>{
+        for node in root.sub_nodes.iter(){
+            match &node.node{
+                Token::ASSIGN => self.assign(node)?,
+                Token::ASTNode(s) if s == "COMP" => self.compound(node)?,
+                Token::ASTNode(s) if s == "Empty" => {},
+                _ => panic!("error in fn compound, wrong statement: {:?}", node.node),
+            }
+        }
+        Ok(())
+    }
+
+    fn assign(&mut self, root: &TreeNode) -> Result<(), RuntimeError>{
+        let name = match &root.sub_nodes[0].node{
+            Token::ID(s) => s.clone(),
+            _ => panic!("error in fn assign, wrong var_name: {:?}", root.sub_nodes[0].node),
+        };
+        let value_node = &root.sub_nodes[1];
+        let value_ty = self.expr(value_node)?;
+        let (local, ty) = {
+            let var = &self.vars[&canonical(&name)];
+            (var.local.clone(), var.ty)
+        };
+
+        match (ty, value_ty){
+            (Type::Real, Type::Integer) => self.line("f64.convert_i64_s"),
+            (Type::Subrange(low, high), _) if root.checks.range => {
+                let location = self.location(value_node.span);
+                let name = self.string(&name);
+                self.line(&format!("i64.const {} i64.const {} {} {} call $range", low, high, location, name));
+            },
+            _ => {},
+        }
+        self.line(&format!("local.set {}", local));
+        self.line(&format!("i32.const 1 local.set {}_set", local));
+        Ok(())
+    }
+
+    //leaves the value of `root` on the stack and returns its base type
+    fn expr(&mut self, root: &TreeNode) -> Result<Type, RuntimeError>{
+        let ty = root.ty.expect("error in fn expr, tree has not been type checked").base();
+        let checked = if root.checks.overflow {1} else {0};
+
+        match &root.node{
+            Token::INTEGER_CONST(n) => match i64::try_from(*n){
+                Ok(n) => self.line(&format!("i64.const {}", n)),
+                Err(_) => return Err(RuntimeError::new(root.span, format!("integer constant {} is too large", n))),
+            },
+            Token::REAL_CONST(n) => self.line(&format!("f64.const {:e}", n)),
+            Token::ID(s) => {
+                let local = self.vars[&canonical(s)].local.clone();
+                let location = self.location(root.span);
+                let message = self.string(&format!("variable `{}` has not been initialized", s));
+                self.line(&format!("local.get {}_set i32.eqz", local));
+                self.line(&format!("if {} {} call $fail end", location, message));
+                self.line(&format!("local.get {}", local));
+            },
+            Token::KEYWORD(s) if s == "DIV" || s == "MOD" => {
+                self.expr(&root.sub_nodes[0])?;
+                self.expr(&root.sub_nodes[1])?;
+                let divisor = self.location(root.sub_nodes[1].span);
+                if s == "DIV"{
+                    let location = self.location(root.span);
+                    self.line(&format!("i32.const {} {} {} call $div_int", checked, divisor, location));
+                }else{
+                    self.line(&format!("{} call $mod_int", divisor));
+                }
+            },
+            Token::OP1(c) | Token::OP2(c) => {
+                let real = ty == Type::Real;
+                for operand in root.sub_nodes.iter(){
+                    if self.expr(operand)? == Type::Integer && real{
+                        self.line("f64.convert_i64_s");
+                    }
+                }
+                let location = self.location(root.span);
+                match (c, ty){
+                    ('+', Type::Integer) => self.line(&format!("i32.const {} {} call $add_int", checked, location)),
+                    ('-', Type::Integer) => self.line(&format!("i32.const {} {} call $sub_int", checked, location)),
+                    ('*', Type::Integer) => self.line(&format!("i32.const {} {} call $mul_int", checked, location)),
+                    ('/', _) => {
+                        let divisor = self.location(root.sub_nodes[1].span);
+                        self.line(&format!("{} {} call $div_real", divisor, location));
+                    },
+                    (c, _) => {
+                        let op = match c {'+' => "add", '-' => "sub", _ => "mul"};
+                        self.line(&format!("f64.{} {} call $real_result", op, location));
+                    },
+                }
+            },
+            Token::UNARY(c) => {
+                self.expr(&root.sub_nodes[0])?;
+                match (c, ty){
+                    ('+', _) => {},
+                    ('-', Type::Real) => self.line("f64.neg"),
+                    _ => {
+                        let location = self.location(root.span);
+                        self.line(&format!("i32.const {} {} call $neg_int", checked, location));
+                    },
+                }
+            },
+            _ => panic!("error in fn expr, wrong node: {:?}", root.node),
+        }
+        Ok(ty)
+    }
+}
+
+//bytes as the contents of a WAT string
+fn escape(data: &[u8]) -> String{
+    let mut out = String::new();
+    for &b in data.iter(){
+        match b{
+            b'"' | b'\\' => {let _ = write!(out, "\\{:02x}", b);},
+            0x20..=0x7e => out.push(b as char),
+            _ => {let _ = write!(out, "\\{:02x}", b);},
+        }
+    }
+    out
+}
@@ -0,0 +1,159 @@
+//Subcommands, input handling and exit status of the binary.
+
+use std::env;
+use std::fs;
+use std::io::Write;
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+const PROGRAM: &str = "PROGRAM Demo;\nVAR a, b : INTEGER; y : REAL;\nBEGIN\n  a := 2; b := 10 * a; y := b / 4\nEND.\n";
+
+struct Output{
+    code: i32,
+    stdout: String,
+    stderr: String,
+}
+
+fn cli(args: &[&str], source: &str) -> Output{
+    let n = COUNTER.fetch_add(1, Ordering::SeqCst);
+    let path = env::temp_dir().join(format!("cli-{}-{}.pas", std::process::id(), n));
+    fs::write(&path, source).unwrap();
+    let out = Command::new(env!("CARGO_BIN_EXE_interpreter-ast")).args(args).arg(&path).output().unwrap();
+    fs::remove_file(&path).unwrap();
+    Output{
+        code: out.status.code().unwrap(),
+        stdout: String::from_utf8_lossy(&out.stdout).into_owned(),
+        stderr: String::from_utf8_lossy(&out.stderr).into_owned(),
+    }
+}
+
+fn cli_stdin(args: &[&str], source: &str) -> Output{
+    let mut child = Command::new(env!("CARGO_BIN_EXE_interpreter-ast")).args(args)
+        .stdin(Stdio::piped()).stdout(Stdio::piped()).stderr(Stdio::piped()).spawn().unwrap();
+    //a command that fails early may exit before reading its input
+    let _ = child.stdin.take().unwrap().write_all(source.as_bytes());
+    let out = child.wait_with_output().unwrap();
+    Output{
+        code: out.status.code().unwrap(),
+        stdout: String::from_utf8_lossy(&out.stdout).into_owned(),
+        stderr: String::from_utf8_lossy(&out.stderr).into_owned(),
+    }
+}
+
+#[test]
+fn run_prints_only_the_variables(){
+    let out = cli(&["run"], PROGRAM);
+    assert_eq!(out.code, 0, "{}", out.stderr);
+    assert_eq!(out.stdout, "a: INTEGER(2)\nb: INTEGER(20)\ny: REAL(5)\n");
+}
+
+#[test]
+fn program_is_read_from_stdin(){
+    let out = cli_stdin(&["run"], PROGRAM);
+    assert_eq!(out.stdout, "a: INTEGER(2)\nb: INTEGER(20)\ny: REAL(5)\n");
+    let out = cli_stdin(&["run", "-"], "PROGRAM P; BEGIN x := 1 END.");
+    assert_eq!(out.code, 1);
+    assert!(out.stderr.starts_with("<stdin>:1:18: type error"), "{}", out.stderr);
+}
+
+#[test]
+fn tokens_are_listed_with_positions(){
+    let out = cli(&["tokens"], "PROGRAM P;\n BEGIN END.");
+    assert_eq!(out.code, 0);
+    let lines: Vec<&str> = out.stdout.lines().map(|l| l.split_once(".pas:").unwrap().1).collect();
+    assert_eq!(lines, vec!["1:1: KEYWORD: PROGRAM", "1:9: variable: P", "1:10: SEMI", "2:2: KEYWORD: BEGIN", "2:8: KEYWORD: END", "2:11: DOT", "2:12: EOF"]);
+}
+
+#[test]
+fn ast_in_every_format(){
+    let out = cli(&["ast"], PROGRAM);
+    assert!(out.stdout.starts_with("Program Demo\n└── Block\n"), "{}", out.stdout);
+    let out = cli(&["ast", "--format", "json"], PROGRAM);
+    assert!(out.stdout.contains("\"kind\": \"PROGRAM\""));
+    let out = cli(&["ast", "--format", "dot", "--types"], PROGRAM);
+    assert!(out.stdout.contains("[label=\"/\\nREAL\"]"), "{}", out.stdout);
+    let out = cli(&["ast", "--spans"], PROGRAM);
+    assert!(out.stdout.starts_with("Program Demo @0..88"), "{}", out.stdout);
+}
+
+#[test]
+fn check_and_symbols_do_not_run_the_program(){
+    let source = "PROGRAM P; VAR a : INTEGER; d : 1..3;\nBEGIN a := 0; a := 1 DIV a END.";
+    let out = cli(&["check"], source);
+    assert_eq!((out.code, out.stdout.as_str()), (0, ""));
+    let out = cli(&["symbols"], source);
+    assert_eq!(out.code, 0);
+    let lines: Vec<&str> = out.stdout.lines().collect();
+    assert!(lines[0].starts_with("a: INTEGER  (") && lines[0].ends_with(".pas:1:16)"), "{}", out.stdout);
+    assert!(lines[1].starts_with("d: 1..3  ("), "{}", out.stdout);
+}
+
+#[test]
+fn exit_status_tells_errors_apart(){
+    assert_eq!(cli(&["run"], "PROGRAM P; BEGIN END").code, 1);
+    assert_eq!(cli(&["check"], "PROGRAM P; VAR a : INTEGER; BEGIN a := 1.5 END.").code, 1);
+    let out = cli(&["run"], "PROGRAM P; VAR a : INTEGER; BEGIN a := 0; a := 1 DIV a END.");
+    assert_eq!(out.code, 2);
+    assert!(out.stderr.contains("runtime error: division by zero"));
+    assert!(out.stdout.is_empty());
+
+    let out = cli(&["frobnicate"], PROGRAM);
+    assert_eq!(out.code, 64);
+    assert!(out.stderr.contains("unknown command `frobnicate`"));
+    assert_eq!(cli(&["ast", "--format", "xml"], PROGRAM).code, 64);
+    assert_eq!(cli(&["run", "--check"], PROGRAM).code, 64);
+    let out = cli(&["ast", "--types"], PROGRAM);
+    assert_eq!(out.code, 64);
+    assert!(out.stderr.contains("`--types` only applies to `--format dot`"), "{}", out.stderr);
+    assert_eq!(cli(&["ast", "--types", "--format", "json"], PROGRAM).code, 64);
+    assert_eq!(cli(&["ast", "--types", "--format", "dot"], PROGRAM).code, 0);
+
+    let out = Command::new(env!("CARGO_BIN_EXE_interpreter-ast")).args(["run", "/nonexistent/x.pas"]).output().unwrap();
+    assert_eq!(out.status.code(), Some(66));
+}
+
+#[test]
+fn disasm_lists_the_bytecode(){
+    let out = cli(&["disasm"], "PROGRAM P; VAR a : INTEGER; y : REAL;\nBEGIN a := 7 DIV 2; y := a / 2 END.");
+    assert_eq!(out.code, 0, "{}", out.stderr);
+    let expected = "\
+.var 0 a
+.var 1 y
+0000  push_int    7
+0001  push_int    2
+0002  check_divisor
+0003  div_int
+0004  store       0 (a)
+0005  load        0 (a)
+0006  to_real
+0007  push_int    2
+0008  to_real
+0009  check_divisor
+0010  div_real
+0011  store       1 (y)
+";
+    assert_eq!(out.stdout, expected);
+}
+
+#[test]
+fn debug_reads_commands_from_stdin(){
+    let path = env::temp_dir().join(format!("cli-{}-debug.pas", std::process::id()));
+    fs::write(&path, PROGRAM).unwrap();
+    let debug = |commands: &str| {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_interpreter-ast")).arg("debug").arg(&path)
+            .stdin(Stdio::piped()).stdout(Stdio::piped()).stderr(Stdio::piped()).spawn().unwrap();
+        child.stdin.take().unwrap().write_all(commands.as_bytes()).unwrap();
+        child.wait_with_output().unwrap()
+    };
+    let finished = debug("watch y\nc\n");
+    let quit = debug("q\n");
+    fs::remove_file(&path).unwrap();
+
+    assert_eq!(finished.status.code(), Some(0));
+    assert!(String::from_utf8_lossy(&finished.stdout).ends_with("watchpoint y: <not initialized> -> REAL(5)\na: INTEGER(2)\nb: INTEGER(20)\ny: REAL(5)\n"));
+    assert_eq!(quit.status.code(), Some(0));
+    assert!(!String::from_utf8_lossy(&quit.stdout).contains("a: INTEGER"));
+    assert_eq!(cli_stdin(&["debug"], PROGRAM).code, 64);
+}
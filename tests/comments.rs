@@ -0,0 +1,68 @@
+//Runs programs with { }, (* *) and // comments through the binary.
+
+use std::env;
+use std::fs;
+use std::process::Command;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+struct Output{
+    success: bool,
+    stdout: String,
+    stderr: String,
+}
+
+fn run(source: &str, args: &[&str]) -> Output{
+    let n = COUNTER.fetch_add(1, Ordering::SeqCst);
+    let path = env::temp_dir().join(format!("comments-{}-{}.pas", std::process::id(), n));
+    fs::write(&path, source).unwrap();
+    let out = Command::new(env!("CARGO_BIN_EXE_interpreter-ast")).arg("run").args(args).arg(&path).output().unwrap();
+    fs::remove_file(&path).unwrap();
+    Output{
+        success: out.status.success(),
+        stdout: String::from_utf8_lossy(&out.stdout).into_owned(),
+        stderr: String::from_utf8_lossy(&out.stderr).into_owned(),
+    }
+}
+
+fn assert_value(out: &Output, line: &str){
+    assert!(out.success, "program failed: {}", out.stderr);
+    assert!(out.stdout.lines().any(|l| l == line), "missing `{}` in:\n{}", line, out.stdout);
+}
+
+fn assert_syntax_error(out: &Output, message: &str){
+    assert!(!out.success, "program should not have run:\n{}", out.stdout);
+    assert!(out.stderr.contains(message), "missing `{}` in:\n{}", message, out.stderr);
+}
+
+#[test]
+fn every_comment_form_is_skipped(){
+    let source = "PROGRAM T; { brace } VAR a, b : INTEGER;\n\
+        BEGIN (* paren\n *) a := 1; // line (* {\n\
+        b := 2 (* } *) + { *) } 3 // to the end\n\
+        END.";
+    let out = run(source, &[]);
+    assert_value(&out, "a: INTEGER(1)");
+    assert_value(&out, "b: INTEGER(5)");
+}
+
+#[test]
+fn comments_nest_only_when_asked(){
+    let source = "PROGRAM T; VAR a : INTEGER; BEGIN\na := 1; { x { y } a := 2; } (* x (* y *) a := 3; *)\nEND.";
+    assert_value(&run(source, &["--nested-comments"]), "a: INTEGER(1)");
+    //without nesting the first `}` closes the comment
+    assert_syntax_error(&run(source, &[]), ":2:27: syntax error: unexpected character `}`");
+}
+
+#[test]
+fn unterminated_comments_are_reported_where_they_start(){
+    let program = "PROGRAM T; VAR a : INTEGER; BEGIN\n  a := 1 ";
+    for (comment, at) in [("{ open", "2:10"), ("(* open *", "2:10"), ("{ x { y } ", "2:10"), ("\n(* x (* y *)", "3:1")]{
+        let out = run(&format!("{}{}", program, comment), &["--nested-comments"]);
+        assert_syntax_error(&out, &format!(":{}: syntax error: unterminated comment", at));
+    }
+    //without nesting `{ x { y }` is a whole comment, and the program ends early
+    let out = run(&format!("{}{{ x {{ y }} ", program), &[]);
+    assert_syntax_error(&out, ":2:20: syntax error:");
+}
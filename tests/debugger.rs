@@ -0,0 +1,110 @@
+use interpreter_ast::debugger::Debugger;
+use interpreter_ast::lexer::Lexer;
+use interpreter_ast::preprocess::SourceMap;
+use interpreter_ast::typecheck::TypeChecker;
+use interpreter_ast::{Interpreter, Visit};
+
+const PROGRAM: &str = "\
+PROGRAM Demo;
+VAR
+  a, b : INTEGER; x : REAL;
+BEGIN
+  a := 2;
+  BEGIN
+    b := a * 10;
+    a := a + 1
+  END;
+  x := a / 4;
+  b := b DIV a
+END.
+";
+
+//runs `source` under the debugger with `commands` as input, returns what it
+//wrote and whether the program ran to its end
+fn session(source: &str, commands: &str) -> (String, bool){
+    let map = SourceMap::single("demo.pas", source);
+    let mut root = Interpreter::new(Lexer::new(source)).parse().unwrap();
+    assert!(TypeChecker::new().check(&mut root).is_ok());
+    let mut output = Vec::new();
+    let mut v = Visit::new();
+    let mut debugger = Debugger::new(&root, &map, commands.as_bytes(), &mut output);
+    let ok = v.visit_with_hook(&root, &mut debugger).is_ok();
+    if ok{
+        debugger.finished(&v);
+    }
+    drop(debugger);
+    (String::from_utf8(output).unwrap(), ok)
+}
+
+//the location lines of the stops
+fn stops(output: &str) -> Vec<&str>{
+    output.lines().filter(|l| l.starts_with("demo.pas:")).collect()
+}
+
+#[test]
+fn stepping_enters_and_leaves_blocks(){
+    let (out, ok) = session(PROGRAM, "step\nstep\nstep\nstep\n");
+    assert!(ok);
+    assert_eq!(stops(&out), vec!["demo.pas:5:3: a := 2;", "demo.pas:6:3: BEGIN", "demo.pas:7:5: b := a * 10;", "demo.pas:8:5: a := a + 1", "demo.pas:10:3: x := a / 4;"]);
+
+    let (out, _) = session(PROGRAM, "next\nnext\nnext\n");
+    assert_eq!(stops(&out), vec!["demo.pas:5:3: a := 2;", "demo.pas:6:3: BEGIN", "demo.pas:10:3: x := a / 4;", "demo.pas:11:3: b := b DIV a"]);
+
+    let (out, _) = session(PROGRAM, "s\ns\nfinish\nfinish\n");
+    assert_eq!(stops(&out), vec!["demo.pas:5:3: a := 2;", "demo.pas:6:3: BEGIN", "demo.pas:7:5: b := a * 10;", "demo.pas:10:3: x := a / 4;"]);
+}
+
+#[test]
+fn breakpoints_stop_once_per_line(){
+    let (out, ok) = session(PROGRAM, "break 8\nbreak 11\nbreak 3\ndelete 11\nc\nc\n");
+    assert!(ok);
+    assert!(out.contains("breakpoint demo.pas:8\nbreakpoint demo.pas:11\nno statement on demo.pas:3\ndeleted breakpoint demo.pas:11\n"), "{}", out);
+    assert_eq!(stops(&out), vec!["demo.pas:5:3: a := 2;", "demo.pas:8:5: a := a + 1"]);
+    assert!(out.contains("breakpoint demo.pas:8\ndemo.pas:8:5"), "{}", out);
+
+    let (out, _) = session("PROGRAM P; VAR a : INTEGER; BEGIN\n a := 1; a := 2; a := 3\nEND.", "c\nbreak 2\nc\n");
+    assert_eq!(stops(&out), vec!["demo.pas:2:2: a := 1; a := 2; a := 3"]);
+}
+
+#[test]
+fn locals_backtrace_and_expressions_see_the_live_values(){
+    let (out, _) = session(PROGRAM, "break 10\nc\nlocals\nbt\nprint a * 2 + b\np x\np a DIV 0\np zz\n");
+    let after = out.split("demo.pas:10:3: x := a / 4;\n").nth(1).unwrap();
+    assert_eq!(after, "\
+a: INTEGER(3)
+b: INTEGER(20)
+x: <not initialized>
+#0 program Demo at demo.pas:10:3
+INTEGER(26)
+<expr>:1:1: runtime error: variable `x` has not been initialized
+<expr>:1:7: runtime error: division by zero
+<expr>:1:1: type error: variable `zz` has not been declared
+");
+}
+
+#[test]
+fn displays_are_printed_at_every_stop(){
+    let (out, _) = session(PROGRAM, "n\ndisplay b - a\nn\nundisplay 1\nn\n");
+    assert!(out.contains("1: b - a = <expr>:1:1: runtime error: variable `b` has not been initialized\n"), "{}", out);
+    assert!(out.contains("demo.pas:10:3: x := a / 4;\n1: b - a = INTEGER(17)\nremoved display 1\ndemo.pas:11:3"), "{}", out);
+}
+
+#[test]
+fn watchpoints_report_changes(){
+    let (out, ok) = session(PROGRAM, "watch B\nwatch y\nc\nc\nc\n");
+    assert!(ok);
+    assert!(out.contains("watchpoint on b\nno variable `y`\n"), "{}", out);
+    assert!(out.contains("watchpoint b: <not initialized> -> INTEGER(20)\ndemo.pas:8:5: a := a + 1\n"), "{}", out);
+    assert!(out.ends_with("watchpoint b: INTEGER(20) -> INTEGER(6)\n"), "{}", out);
+}
+
+#[test]
+fn quit_stops_the_program_and_end_of_input_runs_it(){
+    let (out, ok) = session(PROGRAM, "n\nquit\n");
+    assert!(!ok);
+    assert_eq!(stops(&out).len(), 2);
+
+    let (out, ok) = session(PROGRAM, "break 10\n");
+    assert!(ok);
+    assert_eq!(stops(&out), vec!["demo.pas:5:3: a := 2;"]);
+}
@@ -0,0 +1,33 @@
+use interpreter_ast::dot::DotOptions;
+use interpreter_ast::lexer::Lexer;
+use interpreter_ast::typecheck::TypeChecker;
+use interpreter_ast::{Interpreter, TreeNode};
+
+fn parse(text: &str) -> TreeNode{
+    Interpreter::new(Lexer::new(text)).parse().unwrap()
+}
+
+const SOURCE: &str = "PROGRAM P; VAR a : INTEGER; y : REAL; BEGIN a := -2; y := a / 4 END.";
+
+#[test]
+fn every_node_has_a_label_and_an_edge_from_its_parent(){
+    let dot = parse(SOURCE).to_dot();
+    assert!(dot.starts_with("digraph AST {"));
+    let nodes = dot.lines().filter(|l| l.contains("[label=")).count();
+    let edges = dot.lines().filter(|l| l.contains("->")).count();
+    assert_eq!(nodes, 19);
+    assert_eq!(edges, nodes - 1);
+    assert!(dot.contains("n0 [label=\"PROGRAM\"]"));
+    assert!(dot.contains("[label=\":=\"]"));
+    assert!(dot.contains("[label=\"unary -\"]"));
+    assert!(dot.contains("n0 -> n1;"));
+}
+
+#[test]
+fn annotations_show_types_and_spans(){
+    let mut root = parse(SOURCE);
+    assert!(TypeChecker::new().check(&mut root).is_ok());
+    let dot = root.to_dot_with(DotOptions{types: true, spans: true});
+    assert!(dot.contains("[label=\"/\\nREAL\\n[58..63)\"]"), "{}", dot);
+    assert!(dot.contains("[label=\"PROGRAM\\n[0..68)\"]"), "{}", dot);
+}
@@ -0,0 +1,141 @@
+//Assembles and links the output of `emit-asm` with the system toolchain and
+//checks that the executable prints exactly what `run` prints, errors and exit
+//status included. Skipped off x86-64 Linux or when there is no `cc`.
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+#[derive(Debug, PartialEq)]
+struct Output{
+    code: Option<i32>,
+    stdout: String,
+    stderr: String,
+}
+
+fn output(command: &mut Command) -> Output{
+    let out = command.output().unwrap();
+    Output{
+        code: out.status.code(),
+        stdout: String::from_utf8_lossy(&out.stdout).into_owned(),
+        stderr: String::from_utf8_lossy(&out.stderr).into_owned(),
+    }
+}
+
+fn have_toolchain() -> bool{
+    let found = cfg!(all(target_arch = "x86_64", target_os = "linux"))
+        && Command::new("cc").arg("--version").output().map(|o| o.status.success()).unwrap_or(false);
+    if !found{
+        eprintln!("no x86-64 Linux toolchain, skipping");
+    }
+    found
+}
+
+fn emit(path: &Path, args: &[&str]) -> Output{
+    output(Command::new(env!("CARGO_BIN_EXE_interpreter-ast")).arg("emit-asm").args(args).arg(path))
+}
+
+fn temp_base() -> PathBuf{
+    let n = COUNTER.fetch_add(1, Ordering::SeqCst);
+    env::temp_dir().join(format!("emit-asm-{}-{}", std::process::id(), n))
+}
+
+//runs the program with the interpreter and natively, returns the interpreter output
+fn compare(source: &str, args: &[&str]) -> Output{
+    let base = temp_base();
+    let pas = base.with_extension("pas");
+    let asm = base.with_extension("s");
+    fs::write(&pas, source).unwrap();
+
+    let expected = output(Command::new(env!("CARGO_BIN_EXE_interpreter-ast")).arg("run").args(args).arg(&pas));
+    let emitted = emit(&pas, args);
+    assert_eq!(emitted.code, Some(0), "emit-asm failed: {}", emitted.stderr);
+    fs::write(&asm, &emitted.stdout).unwrap();
+
+    let cc = output(Command::new("cc").arg("-o").arg(&base).arg(&asm));
+    assert_eq!(cc.code, Some(0), "cc failed:\n{}\n{}", cc.stderr, emitted.stdout);
+    assert_eq!(cc.stderr, "", "cc warned on:\n{}", emitted.stdout);
+    let actual = output(&mut Command::new(&base));
+
+    for path in [&pas, &asm, &base]{
+        let _ = fs::remove_file(path);
+    }
+    assert_eq!(expected, actual, "native code disagrees on:\n{}", source);
+    expected
+}
+
+fn program(vars: &str, body: &str) -> String{
+    format!("PROGRAM Test;\nVAR\n{}\nBEGIN\n{}\nEND.\n", vars, body)
+}
+
+#[test]
+fn values_match_the_interpreter(){
+    if !have_toolchain(){
+        return;
+    }
+    let out = compare(&program("a, b, c, d, Unset : INTEGER; r : -5..5;",
+        "a := 7 MOD 3 + -7 MOD 3 * 100;\n\
+         b := -(a DIV 2) * 3 - 1;\n\
+         BEGIN c := b DIV -7 + $7FFFFFFFFFFFFF00; ; END;\n\
+         d := -$7FFFFFFFFFFFFFFF - 1 + +c MOD 1000;\n\
+         r := 3 - 8"), &[]);
+    assert_eq!(out.code, Some(0), "{}", out.stderr);
+    assert!(out.stdout.contains("a: INTEGER(201)"), "{}", out.stdout);
+    assert!(out.stdout.contains("Unset: <not initialized>"), "{}", out.stdout);
+}
+
+#[test]
+fn runtime_errors_match_the_interpreter(){
+    if !have_toolchain(){
+        return;
+    }
+    let cases = [
+        ("a : INTEGER;", "a := 1 DIV (2 - 2)"),
+        ("a : INTEGER;", "a := 7 MOD -2"),
+        ("a : INTEGER;", "a := 7 MOD 0"),
+        ("a : INTEGER;", "a := $7FFFFFFFFFFFFFFF; a := a + 1"),
+        ("a : INTEGER;", "a := -$7FFFFFFFFFFFFFFF - 1; a := a - 1"),
+        ("a : INTEGER;", "a := -$7FFFFFFFFFFFFFFF - 1; a := -a"),
+        ("a : INTEGER;", "a := -$7FFFFFFFFFFFFFFF - 1; a := a DIV -1"),
+        ("a : INTEGER;", "a := $100000000 * $100000000"),
+        ("a, b : INTEGER;", "a := b + 1"),
+        ("r : 1..10;", "r := 10; r := r + 1"),
+        ("r : -$7FFFFFFFFFFF..$7FFFFFFFFFFF;", "r := $7FFFFFFFFFFF; r := r * 2"),
+        ("a : INTEGER;", "a := 5 DIV 0 + 1 DIV (3 - 3)"),
+    ];
+    for (vars, body) in cases.iter(){
+        let out = compare(&program(vars, body), &[]);
+        assert_eq!(out.code, Some(2), "{}", out.stderr);
+    }
+}
+
+#[test]
+fn wrapping_and_directives_match_the_interpreter(){
+    if !have_toolchain(){
+        return;
+    }
+    let out = compare(&program("a, b, c, d : INTEGER; r : 1..10;",
+        "a := $7FFFFFFFFFFFFFFF + 1;\n\
+         b := (-$7FFFFFFFFFFFFFFF - 1) DIV -1;\n\
+         c := -b * 3 - a;\n\
+         {$R-} r := 40 + a; {$R+}\n\
+         d := $100000000 * $100000000"), &["--no-overflow-checks"]);
+    assert_eq!(out.code, Some(0), "{}", out.stderr);
+
+    let out = compare(&program("a : INTEGER;", "{$Q-} a := $7FFFFFFFFFFFFFFF + 1; {$Q+} a := a - 1"), &["--optimize"]);
+    assert_eq!(out.code, Some(2), "{}", out.stderr);
+}
+
+#[test]
+fn real_programs_are_rejected(){
+    let pas = temp_base().with_extension("pas");
+    fs::write(&pas, program("a : INTEGER; x : REAL;", "a := 1; x := a")).unwrap();
+    let out = emit(&pas, &[]);
+    fs::remove_file(&pas).unwrap();
+    assert_eq!(out.code, Some(1));
+    assert!(out.stderr.contains(":3:14: error: emit-asm only supports INTEGER variables, `x` is REAL"), "{}", out.stderr);
+}
@@ -0,0 +1,138 @@
+//Compiles the output of `emit-c` with the system C compiler and checks that
+//the executable prints exactly what `run` prints, errors and exit status
+//included. Skipped when there is no `cc`.
+
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+#[derive(Debug, PartialEq)]
+struct Output{
+    code: Option<i32>,
+    stdout: String,
+    stderr: String,
+}
+
+fn output(command: &mut Command) -> Output{
+    let out = command.output().unwrap();
+    Output{
+        code: out.status.code(),
+        stdout: String::from_utf8_lossy(&out.stdout).into_owned(),
+        stderr: String::from_utf8_lossy(&out.stderr).into_owned(),
+    }
+}
+
+fn have_cc() -> bool{
+    let found = Command::new("cc").arg("--version").output().map(|o| o.status.success()).unwrap_or(false);
+    if !found{
+        eprintln!("no cc found, skipping");
+    }
+    found
+}
+
+//runs the program with the interpreter and as C, returns the interpreter output
+fn compare(source: &str, args: &[&str]) -> Output{
+    let n = COUNTER.fetch_add(1, Ordering::SeqCst);
+    let base = env::temp_dir().join(format!("emit-c-{}-{}", std::process::id(), n));
+    let pas = base.with_extension("pas");
+    let c = base.with_extension("c");
+    fs::write(&pas, source).unwrap();
+
+    let bin = env!("CARGO_BIN_EXE_interpreter-ast");
+    let expected = output(Command::new(bin).arg("run").args(args).arg(&pas));
+    let emitted = output(Command::new(bin).arg("emit-c").args(args).arg(&pas));
+    assert_eq!(emitted.code, Some(0), "emit-c failed: {}", emitted.stderr);
+    fs::write(&c, &emitted.stdout).unwrap();
+
+    let cc = output(Command::new("cc").args(["-std=c99", "-Wall", "-Wextra", "-pedantic", "-o"]).arg(&base).arg(&c).arg("-lm"));
+    assert_eq!(cc.code, Some(0), "cc failed:\n{}\n{}", cc.stderr, emitted.stdout);
+    assert_eq!(cc.stderr, "", "cc warned on:\n{}", emitted.stdout);
+    let actual = output(&mut Command::new(&base));
+
+    for path in [&pas, &c, &base]{
+        let _ = fs::remove_file(Path::new(path));
+    }
+    assert_eq!(expected, actual, "C disagrees on:\n{}", source);
+    expected
+}
+
+fn program(vars: &str, body: &str) -> String{
+    format!("PROGRAM Test;\nVAR\n{}\nBEGIN\n{}\nEND.\n", vars, body)
+}
+
+#[test]
+fn values_match_the_interpreter(){
+    if !have_cc(){
+        return;
+    }
+    let out = compare(&program("a, b, c, unset : INTEGER; x, y, z, w, tiny, neg : REAL; r : -5..5;",
+        "a := 7 MOD 3 + -7 MOD 3 * 100;\n\
+         b := -(a DIV 2) * 3 - 1;\n\
+         c := $7FFFFFFFFFFFFFFF;\n\
+         x := 0.1 + 0.2;\n\
+         y := a / 2 + b;\n\
+         z := 1e300 * 10;\n\
+         w := 123456789.125 - a;\n\
+         tiny := 1.25e-7;\n\
+         neg := -0.0 * 1;\n\
+         r := 3 - 8"), &[]);
+    assert_eq!(out.code, Some(0));
+    assert!(out.stdout.contains("x: REAL(0.30000000000000004)"), "{}", out.stdout);
+    assert!(out.stdout.contains("unset: <not initialized>"), "{}", out.stdout);
+}
+
+#[test]
+fn runtime_errors_match_the_interpreter(){
+    if !have_cc(){
+        return;
+    }
+    let cases = [
+        ("a : INTEGER;", "a := 1 DIV (2 - 2)"),
+        ("a : INTEGER;", "a := 7 MOD -2"),
+        ("a : INTEGER;", "a := 7 MOD 0"),
+        ("x : REAL;", "x := 1 / 0.0"),
+        ("x : REAL;", "x := 1e300 * 1e300"),
+        ("x : REAL;", "x := -1e300 * 1e300"),
+        ("a : INTEGER;", "a := $7FFFFFFFFFFFFFFF; a := a + 1"),
+        ("a : INTEGER;", "a := -$7FFFFFFFFFFFFFFF - 1; a := -a"),
+        ("a : INTEGER;", "a := -$7FFFFFFFFFFFFFFF - 1; a := a DIV -1"),
+        ("a : INTEGER;", "a := $100000000 * $100000000"),
+        ("a, b : INTEGER;", "a := b + 1"),
+        ("r : 1..10;", "r := 10; r := r + 1"),
+        ("a : INTEGER;", "a := 5 DIV 0 + 1 DIV (3 - 3)"),
+    ];
+    for (vars, body) in cases.iter(){
+        let out = compare(&program(vars, body), &[]);
+        assert_eq!(out.code, Some(2), "{}", out.stderr);
+    }
+}
+
+#[test]
+fn wrapping_and_directives_match_the_interpreter(){
+    if !have_cc(){
+        return;
+    }
+    let out = compare(&program("a, b, c, d : INTEGER; r : 1..10;",
+        "a := $7FFFFFFFFFFFFFFF + 1;\n\
+         b := (-$7FFFFFFFFFFFFFFF - 1) DIV -1;\n\
+         c := -b * 3;\n\
+         {$R-} r := 40 + a; {$R+}\n\
+         d := $100000000 * $100000000"), &["--no-overflow-checks"]);
+    assert_eq!(out.code, Some(0), "{}", out.stderr);
+
+    let out = compare(&program("a : INTEGER;", "{$Q-} a := $7FFFFFFFFFFFFFFF + 1; {$Q+} a := a - 1"), &[]);
+    assert_eq!(out.code, Some(2), "{}", out.stderr);
+}
+
+#[test]
+fn optimized_programs_match_the_interpreter(){
+    if !have_cc(){
+        return;
+    }
+    let out = compare(&program("a : INTEGER; x : REAL;", "a := 2 * 3 - 10 + 0; x := -(1.5 * 2) / 1 * a"), &["--optimize"]);
+    assert_eq!(out.code, Some(0), "{}", out.stderr);
+}
@@ -0,0 +1,127 @@
+use std::env;
+use std::fs;
+use std::process::Command;
+
+use interpreter_ast::format::format_source;
+use interpreter_ast::lexer::Lexer;
+use interpreter_ast::{Interpreter, TreeNode};
+
+const MESSY: &str = "{ header }\nprogram Demo; // name\nvar a, bb : integer;   { trailing }\n   y:REAL; d : -1..+5;\n\n\n\
+begin {body}\n a := 2; ;\n(* own line *)\nbb := 10*(a+1) - (3-a) + 10*a div 4;\n  y := 20/7+3.14+-(a*2);\n\
+begin a := $FF end\nend.  {end}\n// bye\n";
+
+fn parse(text: &str) -> TreeNode{
+    Interpreter::new(Lexer::new(text)).parse().unwrap()
+}
+
+#[test]
+fn formatting_keeps_the_ast(){
+    let formatted = format_source(MESSY, false).unwrap();
+    assert!(parse(MESSY).same_shape(&parse(&formatted)), "AST changed:\n{}", formatted);
+}
+
+#[test]
+fn formatting_is_idempotent(){
+    let formatted = format_source(MESSY, false).unwrap();
+    assert_eq!(format_source(&formatted, false).unwrap(), formatted);
+}
+
+#[test]
+fn layout_is_canonical(){
+    let formatted = format_source(MESSY, false).unwrap();
+    let expected = "{ header }\nPROGRAM Demo; // name\nVAR\n   a, bb : INTEGER; { trailing }\n   y     : REAL;\n   d     : -1..+5;\n\n\
+BEGIN {body}\n   a := 2;\n   ;\n   (* own line *)\n   bb := 10 * (a + 1) - (3 - a) + 10 * a DIV 4;\n   y := 20 / 7 + 3.14 + -(a * 2);\n   \
+BEGIN\n      a := $FF\n   END\nEND. {end}\n// bye\n";
+    assert_eq!(formatted, expected);
+}
+
+#[test]
+fn parentheses_follow_precedence(){
+    let source = "PROGRAM P; VAR a : INTEGER; BEGIN a := ((1 - 2) - (3 - 4)) * -(5 DIV (6 MOD 7)) END.";
+    let formatted = format_source(source, false).unwrap();
+    assert!(formatted.contains("a := (1 - 2 - (3 - 4)) * -(5 DIV (6 MOD 7))"), "{}", formatted);
+    assert!(parse(source).same_shape(&parse(&formatted)));
+}
+
+#[test]
+fn check_mode_reports_unformatted_files(){
+    let path = env::temp_dir().join(format!("format-{}.pas", std::process::id()));
+    let bin = env!("CARGO_BIN_EXE_interpreter-ast");
+
+    fs::write(&path, MESSY).unwrap();
+    let out = Command::new(bin).args(["format", "--check"]).arg(&path).output().unwrap();
+    assert!(!out.status.success());
+    assert!(String::from_utf8_lossy(&out.stdout).contains("would reformat"));
+
+    fs::write(&path, format_source(MESSY, false).unwrap()).unwrap();
+    let out = Command::new(bin).args(["format", "--check"]).arg(&path).output().unwrap();
+    fs::remove_file(&path).unwrap();
+    assert!(out.status.success());
+}
+
+#[test]
+fn nested_comments_are_kept_when_enabled(){
+    let source = "PROGRAM P; { outer { inner } still outer }\nVAR a : INTEGER;\nBEGIN a := 1 (* x (* y *) z *) END.\n";
+    assert!(format_source(source, false).is_err());
+    let formatted = format_source(source, true).unwrap();
+    assert!(formatted.contains("{ outer { inner } still outer }"), "{}", formatted);
+    assert!(formatted.contains("(* x (* y *) z *)"), "{}", formatted);
+    assert_eq!(format_source(&formatted, true).unwrap(), formatted);
+
+    let path = env::temp_dir().join(format!("format-nested-{}.pas", std::process::id()));
+    fs::write(&path, source).unwrap();
+    let out = Command::new(env!("CARGO_BIN_EXE_interpreter-ast")).args(["format", "--nested-comments"]).arg(&path).output().unwrap();
+    fs::remove_file(&path).unwrap();
+    assert!(out.status.success(), "{}", String::from_utf8_lossy(&out.stderr));
+    assert_eq!(String::from_utf8_lossy(&out.stdout), formatted);
+}
+
+//exit status and output of `run` on `source`
+fn run(source: &str, n: usize) -> (Option<i32>, String, String){
+    let path = env::temp_dir().join(format!("format-run-{}-{}.pas", std::process::id(), n));
+    fs::write(&path, source).unwrap();
+    let out = Command::new(env!("CARGO_BIN_EXE_interpreter-ast")).arg("run").arg(&path).output().unwrap();
+    fs::remove_file(&path).unwrap();
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    //the line and column of an error may move
+    let message = stderr.split_once("error: ").map_or(String::new(), |(_, m)| m.to_string());
+    (out.status.code(), String::from_utf8_lossy(&out.stdout).into_owned(), message)
+}
+
+#[test]
+fn directives_stay_between_the_same_tokens(){
+    let source = "program P;\nvar a, b : integer; d : {$R-} 1..5;\nbegin\n  a := 9223372036854775807;\n  \
+a := a {$Q-} + 1 {$Q+};  b := a  -  1; {$R-} d := 9 + b {$R+}\nend.\n";
+    let formatted = format_source(source, false).unwrap();
+    for kept in ["d : {$R-} 1..5;", "a := a {$Q-} + 1 {$Q+};", "b := a - 1; {$R-}\n   d := 9 + b {$R+}"]{
+        assert!(formatted.contains(kept), "missing `{}` in:\n{}", kept, formatted);
+    }
+    assert_eq!(format_source(&formatted, false).unwrap(), formatted);
+    let before = run(source, 0);
+    assert_eq!(before.0, Some(2));
+    assert_eq!(before.2, "integer overflow\n");
+    assert_eq!(run(&formatted, 1), before);
+
+    let source = "PROGRAM P; VAR a : INTEGER; BEGIN a := 9223372036854775807; {$Q-} a := a + 1 END.";
+    let formatted = format_source(source, false).unwrap();
+    assert!(formatted.contains("a := 9223372036854775807; {$Q-}\n   a := a + 1\n"), "{}", formatted);
+    assert_eq!(run(&formatted, 2), run(source, 3));
+}
+
+#[test]
+fn conditionals_and_includes_are_not_formatted(){
+    let source = "PROGRAM P; VAR a : INTEGER;\nBEGIN {$IFDEF X} a := 1 {$ELSE} a := 2 {$ENDIF} END.";
+    let err = format_source(source, false).unwrap_err();
+    assert_eq!(err.message, "cannot format files with conditional compilation or includes, found `{$IFDEF X}`");
+    assert_eq!(err.span.start, source.find("{$IFDEF").unwrap());
+    assert!(format_source("PROGRAM P; BEGIN (*$I other.inc*) END.", false).is_err());
+    assert!(format_source("PROGRAM P; {$I-} BEGIN {$DEFINE X} END.", false).is_ok());
+
+    let path = env::temp_dir().join(format!("format-ifdef-{}.pas", std::process::id()));
+    fs::write(&path, source).unwrap();
+    let out = Command::new(env!("CARGO_BIN_EXE_interpreter-ast")).arg("format").arg(&path).output().unwrap();
+    fs::remove_file(&path).unwrap();
+    assert_eq!(out.status.code(), Some(1));
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    assert!(stderr.ends_with(":2:7: error: cannot format files with conditional compilation or includes, found `{$IFDEF X}`\n"), "{}", stderr);
+}
@@ -0,0 +1,140 @@
+use interpreter_ast::ir::{lower, BinOp, Instr, Operand, Program, Terminator, UnOp};
+use interpreter_ast::lexer::{Lexer, Span};
+use interpreter_ast::typecheck::{Type, TypeChecker};
+use interpreter_ast::Interpreter;
+
+fn lowered(vars: &str, body: &str) -> Program{
+    let source = format!("PROGRAM P; VAR {} BEGIN {} END.", vars, body);
+    let mut root = Interpreter::new(Lexer::new(&source)).parse().unwrap();
+    assert!(TypeChecker::new().check(&mut root).is_ok());
+    lower(&root).unwrap()
+}
+
+fn operand_type(program: &Program, operand: Operand) -> Type{
+    match operand{
+        Operand::Temp(t) => program.temps[t],
+        Operand::Int(_) => Type::Integer,
+        Operand::Real(_) => Type::Real,
+    }
+}
+
+#[test]
+fn dump_lists_variables_temporaries_and_blocks(){
+    let program = lowered("a : INTEGER; x : REAL;", "a := 7; x := -a MOD 2 / a");
+    assert_eq!(program.to_string(), "\
+program P
+var a: INTEGER
+var x: REAL
+temp t0: INTEGER
+temp t1: INTEGER
+temp t2: INTEGER
+temp t3: REAL
+temp t4: INTEGER
+temp t5: REAL
+temp t6: REAL
+
+bb0:
+    a := 7
+    t0 = a
+    t1 = -t0
+    check divisor 2
+    check modulus 2
+    t2 = t1 mod 2
+    t3 = real t2
+    t4 = a
+    t5 = real t4
+    check divisor t5
+    t6 = t3 / t5
+    x := t6
+    return
+");
+}
+
+#[test]
+fn control_flow_graph_has_one_returning_block(){
+    let program = lowered("a : INTEGER;", "a := 1; BEGIN a := a + 1 END");
+    assert_eq!(program.blocks.len(), 1);
+    assert_eq!(program.blocks[0].terminator, Terminator::Return);
+    assert!(program.blocks[0].successors().is_empty());
+    assert_eq!(program.predecessors(), vec![Vec::<usize>::new()]);
+    assert_eq!(program.to_dot(), "\
+digraph CFG {
+    node [shape=box, fontname=\"monospace\"];
+    bb0 [label=\"bb0:\\l    a := 1\\l    t0 = a\\l    t1 = t0 + 1\\l    a := t1\\l    return\\l\"];
+}
+");
+}
+
+#[test]
+fn temporaries_are_assigned_once_before_use_and_typed(){
+    let program = lowered("a, b : INTEGER; x : REAL; r : 1..10;",
+        "a := 3; b := (a + 1) * -a DIV 2 - a MOD 4; x := a * 1.5 - b / 3 + -x; r := a + b * 0; x := r");
+    let mut defined = vec![false; program.temps.len()];
+    for instr in program.blocks[0].instrs.iter(){
+        let (dest, operands, operand_ty): (Option<usize>, Vec<Operand>, Option<Type>) = match *instr{
+            Instr::Load(t, v) => {
+                assert_eq!(program.temps[t], program.vars[v].ty.base());
+                (Some(t), vec![], None)
+            },
+            Instr::Store(v, value) => {
+                assert_eq!(operand_type(&program, value), program.vars[v].ty.base());
+                (None, vec![value], None)
+            },
+            Instr::Binary(t, op, left, right) => {
+                let ty = match op{
+                    BinOp::AddReal | BinOp::SubReal | BinOp::MulReal | BinOp::DivReal => Type::Real,
+                    _ => Type::Integer,
+                };
+                assert_eq!(program.temps[t], ty);
+                (Some(t), vec![left, right], Some(ty))
+            },
+            Instr::Unary(t, UnOp::ToReal, operand) => {
+                assert_eq!(program.temps[t], Type::Real);
+                (Some(t), vec![operand], Some(Type::Integer))
+            },
+            Instr::Unary(t, op, operand) => {
+                let ty = if op == UnOp::NegReal {Type::Real} else {Type::Integer};
+                assert_eq!(program.temps[t], ty);
+                (Some(t), vec![operand], Some(ty))
+            },
+            Instr::CheckDivisor(d) | Instr::CheckModulus(d) => (None, vec![d], None),
+            Instr::CheckRange(value, _, _, _) => (None, vec![value], Some(Type::Integer)),
+        };
+        for operand in operands{
+            if let Operand::Temp(t) = operand{
+                assert!(defined[t], "t{} used before it is assigned in `{:?}`", t, instr);
+            }
+            if let Some(ty) = operand_ty{
+                assert_eq!(operand_type(&program, operand), ty, "operand of `{:?}`", instr);
+            }
+        }
+        if let Some(t) = dest{
+            assert!(!defined[t], "t{} assigned twice", t);
+            defined[t] = true;
+        }
+    }
+    assert!(defined.iter().all(|d| *d));
+}
+
+#[test]
+fn checks_follow_the_directives_and_report_at_the_interpreter_spans(){
+    let source = "PROGRAM P; VAR a : INTEGER; r : 1..10; BEGIN a := 9 DIV (a - 1); {$Q-}{$R-} r := a * a; {$R+} r := -a END.";
+    let mut root = Interpreter::new(Lexer::new(source)).parse().unwrap();
+    assert!(TypeChecker::new().check(&mut root).is_ok());
+    let program = lower(&root).unwrap();
+    let block = &program.blocks[0];
+
+    let span_of = |text: &str| {
+        let start = source.find(text).unwrap();
+        Span{start, end: start + text.len()}
+    };
+    let divisor = block.instrs.iter().position(|i| matches!(i, Instr::CheckDivisor(_))).unwrap();
+    assert_eq!(block.spans[divisor], span_of("a - 1"));
+    assert!(block.instrs.contains(&Instr::Binary(1, BinOp::SubInt(true), Operand::Temp(0), Operand::Int(1))));
+    assert!(block.instrs.iter().any(|i| matches!(i, Instr::Binary(_, BinOp::MulInt(false), _, _))));
+    assert!(block.instrs.iter().any(|i| matches!(i, Instr::Unary(_, UnOp::NegInt(false), _))));
+
+    let ranges: Vec<usize> = block.instrs.iter().enumerate().filter(|(_, i)| matches!(i, Instr::CheckRange(..))).map(|(n, _)| n).collect();
+    assert_eq!(ranges.len(), 1);
+    assert_eq!(block.spans[ranges[0]], span_of("-a"));
+}
@@ -0,0 +1,113 @@
+use interpreter_ast::lexer::Lexer;
+use interpreter_ast::typecheck::TypeChecker;
+use interpreter_ast::{Interpreter, TreeNode, Visit};
+
+const SOURCE: &str = "PROGRAM Demo;\nVAR a, b : INTEGER; y : REAL; d : -1..10;\n\
+BEGIN\n  a := $FF; b := -a DIV 2 MOD 7;\n  {$Q-} y := 20 / 7 + 3.14 * 1.5E-3; ;\n  BEGIN d := 5 END\nEND.\n";
+
+fn parse(text: &str) -> TreeNode{
+    Interpreter::new(Lexer::new(text)).parse().unwrap()
+}
+
+fn same_spans(a: &TreeNode, b: &TreeNode) -> bool{
+    a.span == b.span && a.checks == b.checks && a.sub_nodes.iter().zip(b.sub_nodes.iter()).all(|(a, b)| same_spans(a, b))
+}
+
+fn load(json: &str) -> Result<TreeNode, String>{
+    TreeNode::from_json(json).map_err(|e| e.to_string())
+}
+
+fn run(mut root: TreeNode) -> Vec<String>{
+    assert!(TypeChecker::new().check(&mut root).is_ok());
+    let mut v = Visit::new();
+    assert!(v.visit(&root).is_ok());
+    v.var_names.iter().map(|name| format!("{}: {:?}", name, v.var_table[&name.to_lowercase()])).collect()
+}
+
+#[test]
+fn json_round_trip_keeps_the_tree(){
+    let root = parse(SOURCE);
+    let loaded = load(&root.to_json()).unwrap();
+    assert!(root.same_shape(&loaded));
+    assert!(same_spans(&root, &loaded));
+}
+
+#[test]
+fn loaded_tree_runs_like_the_parsed_one(){
+    let root = parse(SOURCE);
+    let loaded = load(&root.to_json()).unwrap();
+    assert_eq!(run(loaded), run(root));
+}
+
+#[test]
+fn json_names_kinds_and_values(){
+    let json = parse("PROGRAM P; VAR x : REAL; BEGIN x := 2.5 + 1 END.").to_json();
+    for field in ["\"kind\": \"PROGRAM\"", "\"kind\": \"OP1\"", "\"value\": \"+\"", "\"value\": 2.5", "\"value\": 1,", "\"value\": \"x\"", "\"value\": \"REAL\"", "\"span\": [0, 48]"]{
+        assert!(json.contains(field), "missing {} in:\n{}", field, json);
+    }
+}
+
+#[test]
+fn hand_written_json_can_be_loaded(){
+    let json = r#"{"kind": "PROGRAM", "children": [
+        {"kind": "ID", "value": "P"},
+        {"kind": "BLOCK", "children": [
+            {"kind": "VARDEC", "children": [{"kind": "ID", "value": "n"}, {"kind": "KEYWORD", "value": "INTEGER"}]},
+            {"kind": "COMP", "children": [
+                {"kind": "ASSIGN", "children": [
+                    {"kind": "ID", "value": "n"},
+                    {"kind": "OP2", "value": "*", "children": [{"kind": "INTEGER_CONST", "value": 6}, {"kind": "INTEGER_CONST", "value": 7}]}
+                ]}
+            ]}
+        ]}
+    ]}"#;
+    assert_eq!(run(load(json).unwrap()), vec!["n: Some(INTEGER(42))".to_string()]);
+}
+
+#[test]
+fn malformed_json_is_rejected(){
+    let err = load(r#"{"kind": "PROGRAM", "children": [}"#).unwrap_err();
+    assert!(err.contains("expected a value"), "{}", err);
+    let err = load(r#"{"kind": "OP1", "value": "*", "children": []}"#).unwrap_err();
+    assert!(err.contains("OP1 value must be"), "{}", err);
+    let err = load(r#"{"kind": "PROGRAM", "children": [{"kind": "ID", "value": "P"}]}"#).unwrap_err();
+    assert!(err.contains("PROGRAM node can not have 1 children"), "{}", err);
+    for name in ["Größe", "café", "1a", "a-b", ""]{
+        let err = load(&format!(r#"{{"kind": "ID", "value": "{}"}}"#, name)).unwrap_err();
+        assert!(err.contains("ID value must be an identifier"), "{}: {}", name, err);
+    }
+    let err = load(r#"{"kind": "ID", "value": "P"}"#).unwrap_err();
+    assert!(err.contains("root node must be a PROGRAM"), "{}", err);
+}
+
+//a program whose BLOCK holds `block`, as JSON text
+fn program_json(block: &str) -> String{
+    format!(r#"{{"kind": "PROGRAM", "children": [{{"kind": "ID", "value": "P"}}, {{"kind": "BLOCK", "children": [{}]}}]}}"#, block)
+}
+
+#[test]
+fn children_of_the_wrong_kind_are_rejected(){
+    let n = r#"{"kind": "ID", "value": "n"}"#;
+    let one = r#"{"kind": "INTEGER_CONST", "value": 1}"#;
+    let integer = r#"{"kind": "KEYWORD", "value": "INTEGER"}"#;
+    let vardec = format!(r#"{{"kind": "VARDEC", "children": [{}, {}]}}"#, n, integer);
+    let comp = |statement: &str| format!(r#"{{"kind": "COMP", "children": [{}]}}"#, statement);
+    let cases = [
+        (program_json(&format!("{}, {}", vardec, comp(&format!(r#"{{"kind": "ASSIGN", "children": [{}, {}]}}"#, one, one)))),
+            "ASSIGN child 0 must be an ID, found `1`"),
+        (r#"{"kind": "PROGRAM", "children": [{"kind": "ID", "value": "P"}, {"kind": "ID", "value": "B"}]}"#.to_string(),
+            "PROGRAM child 1 must be a BLOCK, found `B`"),
+        (program_json(n), "BLOCK child 0 must be a VARDEC or COMP, found `n`"),
+        (program_json(&comp(integer)), "COMP child 0 must be an ASSIGN, COMP or Empty, found `INTEGER`"),
+        (program_json(&format!(r#"{{"kind": "VARDEC", "children": [{}, {}]}}"#, n, one)),
+            "VARDEC child 1 must be INTEGER, REAL or a SUBRANGE, found `1`"),
+        (program_json(&comp(&format!(r#"{{"kind": "ASSIGN", "children": [{}, {{"kind": "OP1", "value": "+", "children": [{}, {}]}}]}}"#, n, one, comp(r#"{"kind": "Empty"}"#)))),
+            "OP1 child 1 must be an expression, found `COMP`"),
+    ];
+    for (json, message) in cases.iter(){
+        let err = load(json).unwrap_err();
+        assert!(err.contains(message), "{} for {}", err, json);
+    }
+    let ok = program_json(&format!("{}, {}", vardec, comp(&format!(r#"{{"kind": "ASSIGN", "children": [{}, {}]}}"#, n, one))));
+    assert_eq!(run(load(&ok).unwrap()), vec!["n: Some(INTEGER(1))".to_string()]);
+}
@@ -0,0 +1,75 @@
+use interpreter_ast::lexer::{reconstruct, Lexer, SpannedToken, Token, TriviaKind};
+
+const SOURCE: &str = "{ header }\r\nPROGRAM Demo; // name\n\
+VAR\ta, b : INTEGER;   \n\
+(* block\n   comment *)\n\
+BEGIN {$Q-}\n  a := $FF; b := a DIV 2  { trailing }\n\
+END. \n// end of file";
+
+fn lex(text: &str) -> Vec<SpannedToken>{
+    Lexer::new(text).preserve_trivia(true).collect::<Result<Vec<_>, _>>().unwrap()
+}
+
+#[test]
+fn trivia_rebuilds_the_source_exactly(){
+    let tokens = lex(SOURCE);
+    assert_eq!(reconstruct(SOURCE, &tokens), SOURCE);
+}
+
+#[test]
+fn comments_on_the_same_line_trail_the_previous_token(){
+    let tokens = lex(SOURCE);
+    let semi = &tokens[2];
+    assert_eq!(semi.token, Token::SEMI);
+    let kinds: Vec<TriviaKind> = semi.trailing.iter().map(|t| t.kind).collect();
+    assert_eq!(kinds, vec![TriviaKind::Whitespace, TriviaKind::Comment, TriviaKind::Newline]);
+}
+
+#[test]
+fn comments_on_their_own_line_lead_the_next_token(){
+    let tokens = lex(SOURCE);
+    let begin = tokens.iter().find(|t| t.token == Token::KEYWORD("BEGIN".to_string())).unwrap();
+    let kinds: Vec<TriviaKind> = begin.leading.iter().map(|t| t.kind).collect();
+    assert_eq!(kinds, vec![TriviaKind::Comment, TriviaKind::Newline]);
+    assert_eq!(begin.trailing[1].kind, TriviaKind::Directive);
+}
+
+#[test]
+fn trivia_is_dropped_by_default(){
+    let tokens: Vec<SpannedToken> = Lexer::new(SOURCE).collect::<Result<Vec<_>, _>>().unwrap();
+    assert!(tokens.iter().all(|t| t.leading.is_empty() && t.trailing.is_empty()));
+    assert_eq!(tokens.last().unwrap().token, Token::EOF);
+}
+
+#[test]
+fn peeking_does_not_consume_tokens(){
+    let source = "a := b + 1;";
+    let expected: Vec<String> = Lexer::new(source).map(|t| format!("{:?}", t.unwrap().token)).collect();
+    assert_eq!(expected.len(), 7);
+
+    let mut lexer = Lexer::new(source);
+    assert_eq!(format!("{:?}", lexer.peek().unwrap().as_ref().unwrap().token), expected[0]);
+    assert_eq!(format!("{:?}", lexer.peek().unwrap().as_ref().unwrap().token), expected[0]);
+    assert_eq!(format!("{:?}", lexer.peek_nth(3).unwrap().as_ref().unwrap().token), expected[3]);
+    assert_eq!(lexer.peek_nth(6).unwrap().as_ref().unwrap().token, Token::EOF);
+    assert!(lexer.peek_nth(7).is_none());
+    assert!(lexer.peek_nth(100).is_none());
+
+    let mut seen = Vec::new();
+    while let Some(t) = lexer.peek().map(|t| format!("{:?}", t.as_ref().unwrap().token)){
+        assert_eq!(format!("{:?}", lexer.next().unwrap().unwrap().token), t);
+        seen.push(t);
+    }
+    assert_eq!(seen, expected);
+    assert!(lexer.next().is_none());
+}
+
+#[test]
+fn peeking_past_an_error_stops_at_the_error(){
+    let mut lexer = Lexer::new("a { open");
+    assert!(lexer.peek_nth(1).unwrap().is_err());
+    assert!(lexer.peek_nth(2).is_none());
+    assert!(lexer.next().unwrap().is_ok());
+    assert_eq!(lexer.next().unwrap().unwrap_err().message, "unterminated comment");
+    assert!(lexer.next().is_none());
+}
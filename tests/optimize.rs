@@ -0,0 +1,66 @@
+use interpreter_ast::lexer::Lexer;
+use interpreter_ast::optimize::optimize;
+use interpreter_ast::typecheck::{Type, TypeChecker};
+use interpreter_ast::{Interpreter, TreeNode};
+
+fn optimized(vars: &str, body: &str) -> TreeNode{
+    let source = format!("PROGRAM P; VAR {} BEGIN {} END.", vars, body);
+    let mut root = Interpreter::new(Lexer::new(&source)).parse().unwrap();
+    assert!(TypeChecker::new().check(&mut root).is_ok());
+    optimize(&mut root);
+    root
+}
+
+//the tree of the value of statement `n`
+fn value(root: &TreeNode, n: usize) -> String{
+    let body = root.sub_nodes[1].sub_nodes.last().unwrap();
+    body.sub_nodes[n].sub_nodes[1].to_string()
+}
+
+fn value_type(root: &TreeNode, n: usize) -> Option<Type>{
+    root.sub_nodes[1].sub_nodes.last().unwrap().sub_nodes[n].sub_nodes[1].ty
+}
+
+#[test]
+fn constants_are_folded_with_their_type(){
+    let root = optimized("a : INTEGER; y : REAL;", "a := 10 * 2 + 3; y := 10 * 2 + 3.14; y := 7 / 2; a := 2 - 5 * 3; y := 6 / 3");
+    assert_eq!(value(&root, 0), "Num 23\n");
+    assert_eq!(value(&root, 1), "Num 23.14\n");
+    assert_eq!(value(&root, 2), "Num 3.5\n");
+    assert_eq!(value(&root, 3), "UnaryOp -\n└── Num 13\n");
+    assert_eq!(value(&root, 4), "Num 2.0\n");
+    assert_eq!(value_type(&root, 0), Some(Type::Integer));
+    assert_eq!(value_type(&root, 4), Some(Type::Real));
+}
+
+#[test]
+fn identities_keep_the_type(){
+    let root = optimized("a : INTEGER; y : REAL;", "a := a * 1 + 0; y := y - 0; y := a * 1.0; y := a / 1; y := y + 0; a := 0 + (1 * a)");
+    assert_eq!(value(&root, 0), "Var a\n");
+    assert_eq!(value(&root, 1), "Var y\n");
+    //INTEGER times REAL 1.0 is REAL, the operation stays to widen a
+    assert_eq!(value(&root, 2), "BinOp *\n├── Var a\n└── Num 1.0\n");
+    assert_eq!(value(&root, 3), "BinOp /\n├── Var a\n└── Num 1\n");
+    //-0.0 + 0 is 0.0, so REAL + 0 is kept
+    assert_eq!(value(&root, 4), "BinOp +\n├── Var y\n└── Num 0\n");
+    assert_eq!(value(&root, 5), "Var a\n");
+}
+
+#[test]
+fn runtime_errors_are_not_folded_away(){
+    let root = optimized("a : INTEGER; y : REAL;", "a := 1 DIV 0; y := 1 / (2 - 2); a := 9223372036854775807 + 1; a := 7 MOD -2");
+    assert_eq!(value(&root, 0), "BinOp DIV\n├── Num 1\n└── Num 0\n");
+    assert_eq!(value(&root, 1), "BinOp /\n├── Num 1\n└── Num 0\n");
+    assert_eq!(value(&root, 2), "BinOp +\n├── Num 9223372036854775807\n└── Num 1\n");
+    assert_eq!(value(&root, 3), "BinOp MOD\n├── Num 7\n└── UnaryOp -\n    └── Num 2\n");
+}
+
+#[test]
+fn empty_statements_are_removed(){
+    let root = optimized("a : INTEGER;", "; a := 1; ; BEGIN ; END; BEGIN a := +a END;");
+    let body = root.sub_nodes[1].sub_nodes.last().unwrap();
+    assert_eq!(body.to_string(), "Compound\n├── Assign\n│   ├── Var a\n│   └── Num 1\n└── Compound\n    └── Assign\n        ├── Var a\n        └── Var a\n");
+
+    let root = optimized("", "; BEGIN END");
+    assert_eq!(root.sub_nodes[1].sub_nodes[0].to_string(), "Compound\n└── NoOp\n");
+}
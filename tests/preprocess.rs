@@ -0,0 +1,165 @@
+//Runs programs that use {$DEFINE}, {$IFDEF} and {$I file} through the binary.
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+struct Output{
+    success: bool,
+    stdout: String,
+    stderr: String,
+}
+
+//an empty directory of its own for each test
+fn temp_dir() -> PathBuf{
+    let n = COUNTER.fetch_add(1, Ordering::SeqCst);
+    let dir = env::temp_dir().join(format!("preprocess-{}-{}", std::process::id(), n));
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn write(path: &Path, text: &str){
+    fs::create_dir_all(path.parent().unwrap()).unwrap();
+    fs::write(path, text).unwrap();
+}
+
+fn run_file(path: &Path, args: &[&str]) -> Output{
+    let out = Command::new(env!("CARGO_BIN_EXE_interpreter-ast")).arg("run").args(args).arg(path).output().unwrap();
+    Output{
+        success: out.status.success(),
+        stdout: String::from_utf8_lossy(&out.stdout).into_owned(),
+        stderr: String::from_utf8_lossy(&out.stderr).into_owned(),
+    }
+}
+
+fn run(source: &str, args: &[&str]) -> Output{
+    let dir = temp_dir();
+    let path = dir.join("main.pas");
+    write(&path, source);
+    let out = run_file(&path, args);
+    fs::remove_dir_all(&dir).unwrap();
+    out
+}
+
+fn program(vars: &str, body: &str) -> String{
+    format!("PROGRAM Test;\nVAR\n{} : INTEGER;\nBEGIN\n{}\nEND.\n", vars, body)
+}
+
+fn assert_value(out: &Output, line: &str){
+    assert!(out.success, "program failed: {}", out.stderr);
+    assert!(out.stdout.lines().any(|l| l == line), "missing `{}` in:\n{}", line, out.stdout);
+}
+
+fn assert_error(out: &Output, message: &str){
+    assert!(!out.success, "program should not have run:\n{}", out.stdout);
+    assert!(out.stderr.contains(message), "missing `{}` in:\n{}", message, out.stderr);
+}
+
+#[test]
+fn define_and_undef_switch_symbols(){
+    let body = "{$DEFINE Debug}{$IFDEF DEBUG}a := 1;{$ENDIF}{$UNDEF debug}{$IFDEF Debug}a := 2;{$ENDIF}b := 3";
+    let out = run(&program("a, b", body), &[]);
+    assert_value(&out, "a: INTEGER(1)");
+    assert_value(&out, "b: INTEGER(3)");
+
+    let body = "{$IFDEF FAST}a := 1{$ELSE}a := 2{$ENDIF}";
+    assert_value(&run(&program("a", body), &["-D", "fast"]), "a: INTEGER(1)");
+    assert_value(&run(&program("a", body), &[]), "a: INTEGER(2)");
+}
+
+#[test]
+fn conditionals_nest(){
+    //each kept branch appends its digit to a
+    let body = "a := 0; {$DEFINE A}\
+        {$IFDEF A}a := a * 10 + 1;{$IFNDEF B}a := a * 10 + 2;{$ELSE}a := a * 10 + 3;{$ENDIF}\
+        {$IFDEF B}a := a * 10 + 4;{$ELSE}a := a * 10 + 5;{$ENDIF}{$ELSE}a := a * 10 + 6;{$ENDIF}\
+        {$IFDEF B}{$DEFINE C}{$IFDEF A}a := a * 10 + 7;{$ELSE}a := a * 10 + 8;{$ENDIF}{$ELSE}a := a * 10 + 9;{$ENDIF}\
+        {$IFDEF C}a := 0;{$ENDIF}\
+        (*$IFNDEF X*)b := 1(*$ENDIF*){$R+}";
+    let out = run(&program("a, b", body), &[]);
+    assert_value(&out, "a: INTEGER(1259)");
+    assert_value(&out, "b: INTEGER(1)");
+}
+
+#[test]
+fn unbalanced_conditionals_are_reported(){
+    let out = run("a\n  {$ELSE}", &[]);
+    assert_error(&out, "main.pas:2:3: preprocessor error: {$ELSE} without matching {$IFDEF}");
+    let out = run("{$IFDEF A}{$ELSE}{$ELSE}{$ENDIF}", &[]);
+    assert_error(&out, "main.pas:1:18: preprocessor error: {$ELSE} without matching {$IFDEF}");
+    let out = run("{$IFDEF A}{$ENDIF}{$ENDIF}", &[]);
+    assert_error(&out, "main.pas:1:19: preprocessor error: {$ENDIF} without matching {$IFDEF}");
+    let out = run("{$IFDEF A}\n{$IFNDEF B}{$ENDIF}", &[]);
+    assert_error(&out, "main.pas:1:1: preprocessor error: {$IFDEF} is never closed by {$ENDIF}");
+}
+
+#[test]
+fn io_switches_are_not_includes(){
+    let out = run(&program("a, b", "{$I+} a := 1; {$I-} b := 2 (*$I+*)"), &[]);
+    assert_value(&out, "a: INTEGER(1)");
+    assert_value(&out, "b: INTEGER(2)");
+}
+
+#[test]
+fn includes_search_the_including_directory_then_the_paths_in_order(){
+    let dir = temp_dir();
+    write(&dir.join("src/own.inc"), "a := 1;");
+    write(&dir.join("one/own.inc"), "a := 2;");
+    write(&dir.join("one/lib.inc"), "b := 1;");
+    write(&dir.join("two/lib.inc"), "b := 2;");
+    write(&dir.join("two/extra.inc"), "c := 2");
+    let main = dir.join("src/main.pas");
+    let (one, two) = (dir.join("one").to_string_lossy().into_owned(), dir.join("two").to_string_lossy().into_owned());
+    let args = ["-I", one.as_str(), "-I", two.as_str()];
+    write(&main, &program("a, b, c", "{$I own.inc} {$INCLUDE lib.inc} {$I 'extra.inc'}"));
+    let out = run_file(&main, &args);
+    write(&main, "\n {$I none.inc}");
+    let missing = run_file(&main, &args);
+    fs::remove_dir_all(&dir).unwrap();
+
+    assert_value(&out, "a: INTEGER(1)");
+    assert_value(&out, "b: INTEGER(1)");
+    assert_value(&out, "c: INTEGER(2)");
+    assert_error(&missing, &format!("{}:2:2: preprocessor error: include file `none.inc` not found", main.to_string_lossy()));
+}
+
+#[test]
+fn locations_inside_included_files(){
+    let dir = temp_dir();
+    write(&dir.join("inc.pas"), "{ included }\n  b := x;\n");
+    let main = dir.join("main.pas");
+    write(&main, &program("a, b, c", "a := y;\n{$I inc.pas}\nc := z"));
+    let out = run_file(&main, &[]);
+    fs::remove_dir_all(&dir).unwrap();
+
+    assert_error(&out, &format!("{}:5:6: type error: variable `y`", main.to_string_lossy()));
+    assert_error(&out, &format!("{}:2:8: type error: variable `x`", dir.join("inc.pas").to_string_lossy()));
+    assert_error(&out, &format!("{}:7:6: type error: variable `z`", main.to_string_lossy()));
+}
+
+#[test]
+fn include_cycles_and_deep_nesting_are_reported(){
+    let dir = temp_dir();
+    write(&dir.join("a.pas"), "{$I b.pas}");
+    write(&dir.join("b.pas"), "{$I ./a.pas}");
+    for n in 0..20{
+        write(&dir.join(format!("deep{}.pas", n)), &format!("{{$I deep{}.pas}}", n + 1));
+    }
+    write(&dir.join("deep20.pas"), "a := 20");
+    let main = dir.join("main.pas");
+    write(&main, &program("a", "{$I a.pas}"));
+    let cycle = run_file(&main, &[]);
+    write(&main, &program("a", "{$I deep0.pas}"));
+    let deep = run_file(&main, &[]);
+    write(&main, &program("a", "{$I deep10.pas}"));
+    let shallow = run_file(&main, &[]);
+    fs::remove_dir_all(&dir).unwrap();
+
+    assert_error(&cycle, "b.pas:1:1: preprocessor error: include file `./a.pas` includes itself");
+    assert_error(&deep, "preprocessor error: includes nested deeper than 16 levels");
+    assert_value(&shallow, "a: INTEGER(20)");
+}
@@ -0,0 +1,58 @@
+use interpreter_ast::repl::{is_complete, Repl};
+
+fn eval_all(repl: &mut Repl, inputs: &[&str]) -> Vec<Result<String, String>>{
+    inputs.iter().map(|i| repl.eval(i)).collect()
+}
+
+#[test]
+fn variables_persist_between_inputs(){
+    let mut repl = Repl::new();
+    let out = eval_all(&mut repl, &["VAR a, b : INTEGER; y : REAL", "a := 3", "b := a * 2; y := b / 4", "a + b", "y", "-a MOD 2;"]);
+    assert_eq!(out, vec![Ok(String::new()), Ok(String::new()), Ok(String::new()), Ok("INTEGER(9)".to_string()), Ok("REAL(1.5)".to_string()), Ok("INTEGER(1)".to_string())]);
+    assert_eq!(repl.eval(":vars"), Ok("a: INTEGER(3)\nb: INTEGER(6)\ny: REAL(1.5)".to_string()));
+}
+
+#[test]
+fn multi_line_blocks_wait_for_end(){
+    assert!(!is_complete("BEGIN\n  a := 1;\n"));
+    assert!(!is_complete("BEGIN BEGIN a := 1 END\n"));
+    assert!(!is_complete("a := 1 { still in a comment\n"));
+    assert!(is_complete("BEGIN\n  a := 1;\nEND\n"));
+    assert!(is_complete("a + 1\n"));
+
+    let mut repl = Repl::new();
+    repl.eval("VAR a : INTEGER").unwrap();
+    repl.eval("BEGIN\n  a := 1;\n  BEGIN a := a + 1 END\nEND\n").unwrap();
+    assert_eq!(repl.eval("a"), Ok("INTEGER(2)".to_string()));
+}
+
+#[test]
+fn errors_leave_the_session_usable(){
+    let mut repl = Repl::new();
+    repl.eval("VAR a : INTEGER").unwrap();
+    assert_eq!(repl.eval("b := 1"), Err("<repl>:1:1: type error: variable `b` has not been declared".to_string()));
+    assert!(repl.eval("a := 1.5").unwrap_err().contains("REAL is not assignment-compatible with INTEGER"));
+    assert!(repl.eval("a DIV 0").unwrap_err().contains("runtime error: variable `a` has not been initialized"));
+    repl.eval("a := 4").unwrap();
+    assert!(repl.eval("a DIV 0").unwrap_err().contains("runtime error: division by zero"));
+    assert!(repl.eval("a +").unwrap_err().contains("syntax error"));
+
+    //a rejected declaration declares nothing
+    assert!(repl.eval("VAR c : REAL; a : REAL").unwrap_err().contains("`a` has already been declared"));
+    assert!(repl.eval("c").unwrap_err().contains("`c` has not been declared"));
+    assert_eq!(repl.eval(":vars"), Ok("a: INTEGER(4)".to_string()));
+}
+
+#[test]
+fn commands_inspect_and_reset(){
+    let mut repl = Repl::new();
+    assert_eq!(repl.eval(":ast 1 + 2 * x"), Ok("BinOp +\n├── Num 1\n└── BinOp *\n    ├── Num 2\n    └── Var x".to_string()));
+    assert_eq!(repl.eval(":tokens x := 1"), Ok("1:1: variable: x\n1:3: ASSIGN\n1:6: INTEGER: 1\n1:7: EOF".to_string()));
+
+    repl.eval("VAR x : INTEGER").unwrap();
+    repl.eval("x := 5").unwrap();
+    repl.eval(":reset").unwrap();
+    assert_eq!(repl.eval(":vars"), Ok(String::new()));
+    assert!(repl.eval("x").is_err());
+    assert!(repl.eval(":frobnicate").unwrap_err().contains("unknown command"));
+}
@@ -0,0 +1,284 @@
+//Pins the semantics of the interpreter by running small programs through the
+//binary and looking at the final variable dump. Every program runs on both
+//backends.
+
+use std::env;
+use std::fs;
+use std::process::Command;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+struct Output{
+    success: bool,
+    stdout: String,
+    stderr: String,
+}
+
+fn run(source: &str) -> Output{
+    run_with(source, &[])
+}
+
+//runs the program with the tree-walker, the bytecode VM and the VM on the
+//optimized tree, which must all agree
+fn run_with(source: &str, args: &[&str]) -> Output{
+    let n = COUNTER.fetch_add(1, Ordering::SeqCst);
+    let path = env::temp_dir().join(format!("semantics-{}-{}.pas", std::process::id(), n));
+    fs::write(&path, source).unwrap();
+    let configs: [&[&str]; 3] = [&["--backend", "tree"], &["--backend", "vm"], &["--backend", "vm", "--optimize"]];
+    let outputs: Vec<Output> = configs.iter().map(|config| {
+        let out = Command::new(env!("CARGO_BIN_EXE_interpreter-ast")).arg("run").args(*config).args(args).arg(&path).output().unwrap();
+        Output{
+            success: out.status.success(),
+            stdout: String::from_utf8_lossy(&out.stdout).into_owned(),
+            stderr: String::from_utf8_lossy(&out.stderr).into_owned(),
+        }
+    }).collect();
+    fs::remove_file(&path).unwrap();
+
+    let tree = &outputs[0];
+    for (config, out) in configs.iter().zip(outputs.iter()).skip(1){
+        assert_eq!((tree.success, &tree.stdout, &tree.stderr), (out.success, &out.stdout, &out.stderr), "{:?} disagrees on:\n{}", config, source);
+    }
+    outputs.into_iter().next().unwrap()
+}
+
+fn program(vars: &str, body: &str) -> String{
+    format!("PROGRAM Test;\nVAR\n{}\nBEGIN\n{}\nEND.\n", vars, body)
+}
+
+fn assert_value(out: &Output, line: &str){
+    assert!(out.success, "program failed: {}", out.stderr);
+    assert!(out.stdout.lines().any(|l| l == line), "missing `{}` in:\n{}", line, out.stdout);
+}
+
+fn assert_type_error(out: &Output, message: &str){
+    assert!(!out.success, "program should not have run:\n{}", out.stdout);
+    assert!(out.stderr.contains(message), "missing `{}` in:\n{}", message, out.stderr);
+}
+
+fn assert_syntax_error(out: &Output, message: &str){
+    assert!(!out.success, "program should not have run:\n{}", out.stdout);
+    assert!(out.stderr.contains(&format!("syntax error: {}", message)), "missing `{}` in:\n{}", message, out.stderr);
+}
+
+fn assert_runtime_error(out: &Output, message: &str){
+    assert!(!out.success, "program should have failed:\n{}", out.stdout);
+    assert!(out.stderr.contains(&format!("runtime error: {}", message)), "missing `{}` in:\n{}", message, out.stderr);
+}
+
+#[test]
+fn undeclared_variable_is_a_type_error(){
+    let out = run(&program("a : INTEGER;", "a := 1;\nb := a;\na := a + c"));
+    assert_type_error(&out, "6:1: type error: variable `b` has not been declared");
+    assert_type_error(&out, "7:10: type error: variable `c` has not been declared");
+}
+
+#[test]
+fn duplicate_declaration_is_rejected_in_any_case(){
+    let out = run(&program("a : INTEGER; A : REAL;", "a := 1"));
+    assert_type_error(&out, ":3:14: type error: variable `A` has already been declared");
+    let out = run(&program("total, Total : INTEGER;", "total := 1"));
+    assert_type_error(&out, "variable `Total` has already been declared");
+    let out = run(&program("a : INTEGER;\nb : REAL; a : 1..2;", "a := 1"));
+    assert_type_error(&out, ":4:11: type error: variable `a` has already been declared");
+}
+
+#[test]
+fn real_is_not_assignable_to_integer_or_subrange(){
+    let out = run(&program("a : INTEGER;", "a := 1.5"));
+    assert_type_error(&out, "5:1: type error: cannot assign to variable `a`: REAL is not assignment-compatible with INTEGER");
+    let out = run(&program("d : 1..10;", "d := 2.0"));
+    assert_type_error(&out, "cannot assign to variable `d`: REAL is not assignment-compatible with 1..10");
+    let out = run(&program("d : -5..5; y : REAL;", "y := 1; d := y"));
+    assert_type_error(&out, "REAL is not assignment-compatible with -5..5, real values are never implicitly truncated");
+}
+
+#[test]
+fn expressions_are_evaluated_with_their_static_type(){
+    let out = run(&program("a : INTEGER; y : REAL;", "a := 7; y := 0.5 + a * 2"));
+    assert_value(&out, "a: INTEGER(7)");
+    assert_value(&out, "y: REAL(14.5)");
+}
+
+#[test]
+fn subrange_is_widened_on_assignment_to_real(){
+    let out = run(&program("y : REAL; d : 1..5;", "d := 2; y := 3; y := y + d"));
+    assert_value(&out, "y: REAL(5)");
+}
+
+#[test]
+fn empty_subrange_is_rejected(){
+    let out = run(&program("d : 10..1;", "d := 1"));
+    assert_type_error(&out, "3:5: type error: empty subrange 10..1");
+    let out = run(&program("d : -1..-3;", ""));
+    assert_type_error(&out, "empty subrange -1..-3");
+    let out = run(&program("d : 3..3;", "d := 3"));
+    assert_value(&out, "d: INTEGER(3)");
+}
+
+#[test]
+fn constant_outside_the_subrange_is_a_type_error(){
+    let out = run(&program("d : 1..10;", "d := 11"));
+    assert_type_error(&out, "constant 11 is out of range 1..10 of variable `d`");
+    let out = run(&program("d : 1..5;", "d := -(-9)"));
+    assert_type_error(&out, "5:6: type error: constant 9 is out of range 1..5 of variable `d`");
+    let out = run(&program("d : -10..-5;", "d := -(+7)"));
+    assert_value(&out, "d: INTEGER(-7)");
+}
+
+#[test]
+fn disjoint_subranges_are_not_assignment_compatible(){
+    let out = run(&program("d : 1..10; e : 20..30;", "e := 25; d := e"));
+    assert_type_error(&out, "cannot assign to variable `d`: subrange 20..30 has no value in common with 1..10");
+    let out = run(&program("d : 1..10; e : 10..30;", "e := 10; d := e"));
+    assert_value(&out, "d: INTEGER(10)");
+}
+
+#[test]
+fn value_outside_the_subrange_is_a_runtime_error(){
+    let out = run(&program("d : 1..10; e : 5..20;", "e := 15; d := e"));
+    assert_runtime_error(&out, "value 15 out of range 1..10 of variable `d`");
+    assert!(out.stderr.contains("5:15:"), "wrong position: {}", out.stderr);
+    let out = run(&program("a : INTEGER; d : -3..3;", "a := 2; d := a - 6"));
+    assert_runtime_error(&out, "value -4 out of range -3..3 of variable `d`");
+}
+
+#[test]
+fn slash_on_integers_yields_real(){
+    let out = run(&program("y : REAL;", "y := 7 / 2"));
+    assert_value(&out, "y: REAL(3.5)");
+}
+
+#[test]
+fn slash_result_is_not_assignable_to_integer(){
+    let out = run(&program("a : INTEGER;", "a := 4 / 2"));
+    assert_type_error(&out, "REAL is not assignment-compatible with INTEGER");
+}
+
+#[test]
+fn div_truncates_toward_zero(){
+    let out = run(&program("a, b : INTEGER;", "a := 7 DIV 2; b := -7 DIV 2"));
+    assert_value(&out, "a: INTEGER(3)");
+    assert_value(&out, "b: INTEGER(-3)");
+}
+
+#[test]
+fn mod_result_is_never_negative(){
+    let out = run(&program("a, b : INTEGER;", "a := 7 MOD 3; b := -7 MOD 3"));
+    assert_value(&out, "a: INTEGER(1)");
+    assert_value(&out, "b: INTEGER(2)");
+}
+
+#[test]
+fn div_on_real_operand_is_a_type_error(){
+    let out = run(&program("a : INTEGER;", "a := 7.5 DIV 2"));
+    assert_type_error(&out, "DIV requires INTEGER operands, found REAL");
+}
+
+#[test]
+fn mod_on_real_operand_is_a_type_error(){
+    let out = run(&program("a : INTEGER; y : REAL;", "y := 2.0; a := 7 MOD y"));
+    assert_type_error(&out, "MOD requires INTEGER operands, found REAL");
+}
+
+#[test]
+fn mixed_operands_yield_real(){
+    let out = run(&program("y : REAL;", "y := 1 + 0.5 * 2"));
+    assert_value(&out, "y: REAL(2)");
+}
+
+#[test]
+fn integer_is_widened_on_assignment_to_real(){
+    let out = run(&program("y : REAL;", "y := 2 * 3"));
+    assert_value(&out, "y: REAL(6)");
+}
+
+#[test]
+fn integer_division_by_zero_is_a_runtime_error(){
+    let out = run(&program("a, b : INTEGER;", "b := 0; a := 7 DIV b"));
+    assert_runtime_error(&out, "division by zero");
+}
+
+#[test]
+fn real_division_by_zero_is_a_runtime_error(){
+    let out = run(&program("y : REAL;", "y := 1 / 0"));
+    assert_runtime_error(&out, "division by zero");
+}
+
+#[test]
+fn integer_overflow_is_trapped(){
+    let out = run(&program("a : INTEGER;", "a := 9223372036854775807; a := a + 1"));
+    assert_runtime_error(&out, "integer overflow");
+}
+
+#[test]
+fn integer_overflow_wraps_when_checks_are_off(){
+    let out = run_with(&program("a : INTEGER;", "a := 9223372036854775807; a := a + 1"), &["--no-overflow-checks"]);
+    assert_value(&out, "a: INTEGER(-9223372036854775808)");
+}
+
+#[test]
+fn overflow_directive_switches_checks_for_the_following_code(){
+    let body = "a := 9223372036854775807;\n{$Q-} a := a + 1; {$Q+}\nb := a - 1";
+    let out = run(&program("a, b : INTEGER;", body));
+    assert_runtime_error(&out, "integer overflow");
+    assert!(out.stderr.contains(".pas:7:6:"), "wrong position: {}", out.stderr);
+}
+
+#[test]
+fn range_directive_disables_subrange_checks(){
+    let out = run(&program("a : INTEGER; d : 1..10;", "a := 11; {$R-} d := a"));
+    assert_value(&out, "d: INTEGER(11)");
+    let out = run(&program("a : INTEGER; d : 1..10;", "a := 11; d := a"));
+    assert_runtime_error(&out, "value 11 out of range 1..10");
+}
+
+#[test]
+fn numeric_literals_in_every_radix(){
+    let out = run(&program("a, b, c, d : INTEGER; y : REAL;", "a := $FF; b := %1010; c := &17; d := 1_000_000; y := 1.5E-3"));
+    assert_value(&out, "a: INTEGER(255)");
+    assert_value(&out, "b: INTEGER(10)");
+    assert_value(&out, "c: INTEGER(15)");
+    assert_value(&out, "d: INTEGER(1000000)");
+    assert_value(&out, "y: REAL(0.0015)");
+}
+
+#[test]
+fn exponent_makes_a_literal_real(){
+    let out = run(&program("a : INTEGER;", "a := 2e3"));
+    assert_type_error(&out, "REAL is not assignment-compatible with INTEGER");
+}
+
+#[test]
+fn integer_literal_out_of_range_is_rejected(){
+    let out = run(&program("a : INTEGER;", "a := 9223372036854775808"));
+    assert_syntax_error(&out, "integer literal is out of range");
+}
+
+#[test]
+fn keywords_and_identifiers_ignore_case(){
+    let source = "program Test;\nvar Total_Sum, _n : integer;\nbegin\n  _N := 4; TOTAL_SUM := _n Div 2\nend.\n";
+    let out = run(source);
+    assert_value(&out, "Total_Sum: INTEGER(2)");
+    assert_value(&out, "_n: INTEGER(4)");
+}
+
+#[test]
+fn mixed_expressions_agree_on_both_backends(){
+    let body = "a := 7; b := -a MOD 3 * (a DIV -2) - +a; y := a / 2 + b * 1.5 - -a; d := b + 8;\n\
+{$Q-} a := 9223372036854775807; a := a * 2 + 1; b := -(-9223372036854775807 - 1); {$Q+}";
+    let out = run(&program("a, b : INTEGER; y : REAL; d : -10..10;", body));
+    assert_value(&out, "a: INTEGER(-1)");
+    assert_value(&out, "b: INTEGER(-9223372036854775808)");
+    assert_value(&out, "y: REAL(-9)");
+    assert_value(&out, "d: INTEGER(-5)");
+}
+
+#[test]
+fn runtime_errors_agree_on_both_backends(){
+    for body in ["a := b", "a := 5 MOD -2", "y := 1e300 * 1e300", "a := -9223372036854775807 - 1; a := -a", "d := 0; a := 3; d := a * 4"]{
+        let out = run(&program("a, b : INTEGER; y : REAL; d : 0..10;", body));
+        assert!(!out.success, "{} should fail", body);
+    }
+}
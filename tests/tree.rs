@@ -0,0 +1,44 @@
+use interpreter_ast::lexer::Lexer;
+use interpreter_ast::{Interpreter, TreeNode};
+
+fn parse(text: &str) -> TreeNode{
+    Interpreter::new(Lexer::new(text)).parse().unwrap()
+}
+
+const SOURCE: &str = "PROGRAM Part10AST; VAR a : INTEGER; d : -1..10; y : REAL;\n\
+BEGIN a := 2; ; BEGIN y := -a / 3.5 END; d := a MOD 4 END.";
+
+#[test]
+fn tree_shows_every_node_kind(){
+    let expected = "\
+Program Part10AST
+└── Block
+    ├── VarDecl a: INTEGER
+    ├── VarDecl d: -1..10
+    ├── VarDecl y: REAL
+    └── Compound
+        ├── Assign
+        │   ├── Var a
+        │   └── Num 2
+        ├── NoOp
+        ├── Compound
+        │   └── Assign
+        │       ├── Var y
+        │       └── BinOp /
+        │           ├── UnaryOp -
+        │           │   └── Var a
+        │           └── Num 3.5
+        └── Assign
+            ├── Var d
+            └── BinOp MOD
+                ├── Var a
+                └── Num 4
+";
+    assert_eq!(parse(SOURCE).to_string(), expected);
+}
+
+#[test]
+fn alternate_form_adds_spans(){
+    let tree = format!("{:#}", parse("PROGRAM P; BEGIN END."));
+    assert_eq!(tree, "Program P @0..21\n└── Block @11..20\n    └── Compound @11..20\n        └── NoOp @17..17\n");
+}
@@ -0,0 +1,155 @@
+use std::convert::Infallible;
+
+use interpreter_ast::lexer::{Lexer, Span, Token};
+use interpreter_ast::visitor::{Visitor, VisitorMut};
+use interpreter_ast::{Interpreter, TreeNode};
+
+fn parse(text: &str) -> TreeNode{
+    Interpreter::new(Lexer::new(text)).parse().unwrap()
+}
+
+const SOURCE: &str = "PROGRAM P; VAR a, b : INTEGER; d : -1..+5;\n\
+BEGIN a := +2; ; BEGIN b := a * +(a - 1) END; d := -a DIV 2 END.";
+
+//names of variables read in expressions, in visiting order
+#[derive(Default)]
+struct Reads{
+    names: Vec<String>,
+    in_assign_target: bool,
+}
+
+impl Visitor for Reads{
+    type Output = ();
+    type Error = Infallible;
+
+    fn visit_var_decl(&mut self, _node: &TreeNode) -> Result<(), Infallible>{
+        Ok(())
+    }
+
+    fn visit_assign(&mut self, node: &TreeNode) -> Result<(), Infallible>{
+        self.in_assign_target = true;
+        self.visit_var(&node.sub_nodes[0])?;
+        self.in_assign_target = false;
+        self.visit_node(&node.sub_nodes[1])
+    }
+
+    fn visit_var(&mut self, node: &TreeNode) -> Result<(), Infallible>{
+        if let Token::ID(s) = &node.node{
+            if !self.in_assign_target{
+                self.names.push(s.clone());
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Default)]
+struct Counts{
+    statements: usize,
+    nums: usize,
+}
+
+impl Visitor for Counts{
+    type Output = ();
+    type Error = Infallible;
+
+    fn visit_assign(&mut self, node: &TreeNode) -> Result<(), Infallible>{
+        self.statements += 1;
+        self.walk_assign(node)
+    }
+
+    fn visit_empty(&mut self, _node: &TreeNode) -> Result<(), Infallible>{
+        self.statements += 1;
+        Ok(())
+    }
+
+    fn visit_num(&mut self, _node: &TreeNode) -> Result<(), Infallible>{
+        self.nums += 1;
+        Ok(())
+    }
+}
+
+//drops unary plus, which never changes a value
+struct DropUnaryPlus;
+
+impl VisitorMut for DropUnaryPlus{
+    type Output = ();
+    type Error = Infallible;
+
+    fn visit_unary_op(&mut self, node: &mut TreeNode) -> Result<(), Infallible>{
+        self.walk_unary_op(node)?;
+        if matches!(node.node, Token::UNARY('+')){
+            *node = node.sub_nodes.remove(0);
+        }
+        Ok(())
+    }
+}
+
+//value of an integer expression over no variables, the first variable is an error
+struct Evaluate;
+
+impl Visitor for Evaluate{
+    type Output = i128;
+    type Error = Span;
+
+    fn visit_binary_op(&mut self, node: &TreeNode) -> Result<i128, Span>{
+        let left = self.visit_node(&node.sub_nodes[0])?;
+        let right = self.visit_node(&node.sub_nodes[1])?;
+        Ok(match &node.node{
+            Token::OP1('+') => left + right,
+            Token::OP1('-') => left - right,
+            Token::OP2('*') => left * right,
+            _ => left / right,
+        })
+    }
+
+    fn visit_unary_op(&mut self, node: &TreeNode) -> Result<i128, Span>{
+        let value = self.visit_node(&node.sub_nodes[0])?;
+        Ok(if matches!(node.node, Token::UNARY('-')) {-value} else {value})
+    }
+
+    fn visit_var(&mut self, node: &TreeNode) -> Result<i128, Span>{
+        Err(node.span)
+    }
+
+    fn visit_num(&mut self, node: &TreeNode) -> Result<i128, Span>{
+        match &node.node{
+            Token::INTEGER_CONST(n) => Ok(*n as i128),
+            _ => Err(node.span),
+        }
+    }
+}
+
+#[test]
+fn visitor_overrides_only_what_it_needs(){
+    let root = parse(SOURCE);
+    let mut reads = Reads::default();
+    let Ok(()) = reads.visit_program(&root);
+    assert_eq!(reads.names, vec!["a", "a", "a"]);
+
+    let mut counts = Counts::default();
+    let Ok(()) = counts.visit_node(&root);
+    assert_eq!(counts.statements, 4);
+    //2 and 1 in statements, 2 in the last one, -1 and +5 in the subrange
+    assert_eq!(counts.nums, 5);
+}
+
+#[test]
+fn mutable_visitor_rewrites_in_place(){
+    let mut root = parse(SOURCE);
+    let Ok(()) = DropUnaryPlus.visit_node(&mut root);
+    let expected = parse("PROGRAM P; VAR a, b : INTEGER; d : -1..5;\n\
+BEGIN a := 2; ; BEGIN b := a * (a - 1) END; d := -a DIV 2 END.");
+    assert!(root.same_shape(&expected), "{}", root);
+}
+
+#[test]
+fn visits_return_values_and_stop_at_the_first_error(){
+    //the value assigned by the only statement
+    let value = |text: &str| parse(&format!("PROGRAM P; BEGIN x := {} END.", text)).sub_nodes[1].sub_nodes[0].sub_nodes[0].sub_nodes[1].clone();
+    assert_eq!(Evaluate.visit_node(&value("2 * -(3 + 4) DIV 7")), Ok(-2));
+    //the walk stops at `a`, `b` is never visited
+    assert_eq!(Evaluate.visit_node(&value("1 + a * b")), Err(Span::new(26, 27)));
+    //a walk drops the values of the children
+    assert_eq!(Evaluate.visit_node(&parse("PROGRAM P; BEGIN END.")), Ok(0));
+}
@@ -0,0 +1,351 @@
+//Checks the structure of the modules `emit-wat` produces: the imports and
+//exports the host relies on, one local per variable, the strings in memory,
+//and that every function body type checks on the operand stack.
+
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+#[derive(Debug, Clone)]
+enum Sexp{
+    Atom(String),
+    Str(Vec<u8>),
+    List(Vec<Sexp>),
+}
+
+impl Sexp{
+    fn atom(&self) -> Option<&str>{
+        match self{
+            Sexp::Atom(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    fn list(&self) -> &[Sexp]{
+        match self{
+            Sexp::List(items) => items,
+            _ => &[],
+        }
+    }
+
+    //a list whose first atom is `head`
+    fn is(&self, head: &str) -> bool{
+        self.list().first().and_then(Sexp::atom) == Some(head)
+    }
+}
+
+fn parse(text: &str) -> Sexp{
+    let bytes = text.as_bytes();
+    let mut stack: Vec<Vec<Sexp>> = vec![Vec::new()];
+    let mut i = 0;
+    while i < bytes.len(){
+        match bytes[i]{
+            b';' if bytes.get(i + 1) == Some(&b';') => {
+                while i < bytes.len() && bytes[i] != b'\n' {i += 1;}
+            },
+            b'(' => {stack.push(Vec::new()); i += 1;},
+            b')' => {
+                let list = stack.pop().unwrap();
+                stack.last_mut().expect("unbalanced `)`").push(Sexp::List(list));
+                i += 1;
+            },
+            b'"' => {
+                let mut s = Vec::new();
+                i += 1;
+                while bytes[i] != b'"'{
+                    if bytes[i] == b'\\'{
+                        s.push(u8::from_str_radix(&text[i + 1..i + 3], 16).unwrap());
+                        i += 3;
+                    }else{
+                        s.push(bytes[i]);
+                        i += 1;
+                    }
+                }
+                stack.last_mut().unwrap().push(Sexp::Str(s));
+                i += 1;
+            },
+            b if b.is_ascii_whitespace() => i += 1,
+            _ => {
+                let start = i;
+                while i < bytes.len() && !bytes[i].is_ascii_whitespace() && bytes[i] != b'(' && bytes[i] != b')' {i += 1;}
+                stack.last_mut().unwrap().push(Sexp::Atom(text[start..i].to_string()));
+            },
+        }
+    }
+    assert_eq!(stack.len(), 1, "unbalanced `(`");
+    let mut top = stack.pop().unwrap();
+    assert_eq!(top.len(), 1, "more than one top level form");
+    top.pop().unwrap()
+}
+
+#[derive(Default)]
+struct Func{
+    export: Option<String>,
+    params: Vec<String>,
+    results: Vec<String>,
+    //name to type, for params and locals
+    locals: HashMap<String, String>,
+    local_order: Vec<(String, String)>,
+    body: Vec<Sexp>,
+}
+
+fn func(items: &[Sexp]) -> (String, Func){
+    let name = items[1].atom().unwrap().to_string();
+    let mut f = Func::default();
+    for item in items[2..].iter(){
+        //after the first instruction a list is part of it, as in `if (result i32)`
+        if !f.body.is_empty(){
+            f.body.push(item.clone());
+            continue;
+        }
+        let list = item.list();
+        let atoms = || list[1..].iter().filter_map(Sexp::atom);
+        if item.is("export"){
+            if let Sexp::Str(s) = &list[1] {f.export = Some(String::from_utf8(s.clone()).unwrap());}
+        }else if item.is("param") || item.is("local"){
+            let atoms: Vec<&str> = atoms().collect();
+            let typed: Vec<(String, String)> = if atoms[0].starts_with('$'){
+                vec![(atoms[0].to_string(), atoms[1].to_string())]
+            }else{
+                atoms.iter().map(|t| (String::new(), t.to_string())).collect()
+            };
+            for (n, t) in typed{
+                if item.is("param") {f.params.push(t.clone());}
+                else {f.local_order.push((n.clone(), t.clone()));}
+                f.locals.insert(n, t);
+            }
+        }else if item.is("result"){
+            f.results.extend(atoms().map(str::to_string));
+        }else{
+            f.body.push(item.clone());
+        }
+    }
+    (name, f)
+}
+
+struct Module{
+    imports: Vec<(String, String)>,
+    memory_export: Option<String>,
+    data: Vec<u8>,
+    funcs: HashMap<String, Func>,
+}
+
+fn module(text: &str) -> Module{
+    let root = parse(text);
+    assert!(root.is("module"), "not a module");
+    let mut m = Module{imports: Vec::new(), memory_export: None, data: Vec::new(), funcs: HashMap::new()};
+    for field in root.list()[1..].iter(){
+        let items = field.list();
+        if field.is("import"){
+            let s = |i: usize| match &items[i] {Sexp::Str(s) => String::from_utf8(s.clone()).unwrap(), _ => panic!("import without names")};
+            m.imports.push((s(1), s(2)));
+            let (name, f) = func(items[3].list());
+            m.funcs.insert(name, f);
+        }else if field.is("memory"){
+            if let Sexp::Str(s) = &items[1].list()[1] {m.memory_export = Some(String::from_utf8(s.clone()).unwrap());}
+        }else if field.is("data"){
+            assert!(items[1].is("i32.const") && items[1].list()[1].atom() == Some("0"));
+            if let Sexp::Str(s) = &items[2] {m.data.extend_from_slice(s);}
+        }else if field.is("func"){
+            let (name, f) = func(items);
+            m.funcs.insert(name, f);
+        }else{
+            panic!("unexpected module field {:?}", field);
+        }
+    }
+    m
+}
+
+//the operand stack types an instruction pops and pushes
+fn signature(op: &str) -> Option<(&'static [&'static str], &'static [&'static str])>{
+    Some(match op{
+        "i64.add" | "i64.sub" | "i64.mul" | "i64.div_s" | "i64.rem_s" | "i64.xor" | "i64.and" => (&["i64", "i64"], &["i64"]),
+        "i64.eq" | "i64.ne" | "i64.lt_s" | "i64.gt_s" => (&["i64", "i64"], &["i32"]),
+        "i64.eqz" => (&["i64"], &["i32"]),
+        "i32.eqz" => (&["i32"], &["i32"]),
+        "i32.and" | "i32.or" => (&["i32", "i32"], &["i32"]),
+        "f64.add" | "f64.sub" | "f64.mul" | "f64.div" => (&["f64", "f64"], &["f64"]),
+        "f64.eq" | "f64.ne" => (&["f64", "f64"], &["i32"]),
+        "f64.neg" => (&["f64"], &["f64"]),
+        "f64.convert_i64_s" => (&["i64"], &["f64"]),
+        _ => return None,
+    })
+}
+
+//type checks the flat instructions of a function body
+fn validate(name: &str, f: &Func, funcs: &HashMap<String, Func>){
+    let body: Vec<&Sexp> = f.body.iter().collect();
+    //(stack, result types, unreachable) per open block
+    let mut blocks: Vec<(Vec<String>, Vec<String>, bool)> = vec![(Vec::new(), f.results.clone(), false)];
+    let mut i = 0;
+    let pop = |blocks: &mut Vec<(Vec<String>, Vec<String>, bool)>, want: &str, op: &str|{
+        let block = blocks.last_mut().unwrap();
+        match block.0.pop(){
+            Some(t) => assert_eq!(t, want, "{}: `{}` pops {} but found {}", name, op, want, t),
+            None => assert!(block.2, "{}: `{}` on an empty stack", name, op),
+        }
+    };
+    while i < body.len(){
+        let op = body[i].atom().unwrap_or_else(|| panic!("{}: unexpected {:?}", name, body[i]));
+        let mut operand = || {i += 1; body[i].atom().unwrap().to_string()};
+        match op{
+            "i32.const" | "i64.const" | "f64.const" => {
+                let value = operand();
+                let ok = match op{
+                    "f64.const" => value.parse::<f64>().is_ok(),
+                    "i32.const" => value.parse::<i32>().is_ok(),
+                    _ => value.parse::<i64>().is_ok(),
+                };
+                assert!(ok, "{}: bad immediate `{} {}`", name, op, value);
+                blocks.last_mut().unwrap().0.push(op[..3].to_string());
+            },
+            "local.get" => {
+                let local = operand();
+                let t = f.locals.get(&local).unwrap_or_else(|| panic!("{}: unknown local {}", name, local)).clone();
+                blocks.last_mut().unwrap().0.push(t);
+            },
+            "local.set" => {
+                let local = operand();
+                let t = f.locals.get(&local).unwrap_or_else(|| panic!("{}: unknown local {}", name, local)).clone();
+                pop(&mut blocks, &t, op);
+            },
+            "call" => {
+                let callee = operand();
+                let g = funcs.get(&callee).unwrap_or_else(|| panic!("{}: call to unknown {}", name, callee));
+                for t in g.params.iter().rev(){
+                    pop(&mut blocks, t, &callee);
+                }
+                blocks.last_mut().unwrap().0.extend(g.results.iter().cloned());
+            },
+            "if" => {
+                pop(&mut blocks, "i32", op);
+                let mut results = Vec::new();
+                if body.get(i + 1).is_some_and(|s| s.is("result")){
+                    i += 1;
+                    results = body[i].list()[1..].iter().map(|s| s.atom().unwrap().to_string()).collect();
+                }
+                blocks.push((Vec::new(), results, false));
+            },
+            "else" | "end" => {
+                let (stack, results, unreachable) = blocks.pop().unwrap();
+                assert!(stack == results || (unreachable && stack.is_empty()), "{}: block ends with {:?}, expected {:?}", name, stack, results);
+                if op == "else"{
+                    blocks.push((Vec::new(), results, false));
+                }else{
+                    blocks.last_mut().unwrap().0.extend(results);
+                }
+            },
+            "unreachable" | "return" => {
+                if op == "return"{
+                    for t in f.results.iter().rev(){
+                        pop(&mut blocks, t, op);
+                    }
+                }
+                let block = blocks.last_mut().unwrap();
+                block.0.clear();
+                block.2 = true;
+            },
+            _ => {
+                let (pops, pushes) = signature(op).unwrap_or_else(|| panic!("{}: unknown instruction {}", name, op));
+                for t in pops.iter().rev(){
+                    pop(&mut blocks, t, op);
+                }
+                blocks.last_mut().unwrap().0.extend(pushes.iter().map(|t| t.to_string()));
+            },
+        }
+        i += 1;
+    }
+    assert_eq!(blocks.len(), 1, "{}: unclosed block", name);
+    let (stack, results, unreachable) = &blocks[0];
+    assert!(stack == results || (*unreachable && stack.is_empty()), "{}: body leaves {:?}, expected {:?}", name, stack, results);
+}
+
+//the module of the program at `path`, every function validated
+fn emit_file(path: &Path, args: &[&str]) -> Module{
+    let out = Command::new(env!("CARGO_BIN_EXE_interpreter-ast")).arg("emit-wat").args(args).arg(path).output().unwrap();
+    assert!(out.status.success(), "emit-wat failed: {}", String::from_utf8_lossy(&out.stderr));
+    let m = module(&String::from_utf8(out.stdout).unwrap());
+    for (name, f) in m.funcs.iter(){
+        validate(name, f, &m.funcs);
+    }
+    m
+}
+
+fn temp_file(source: &str) -> PathBuf{
+    let n = COUNTER.fetch_add(1, Ordering::SeqCst);
+    let path = env::temp_dir().join(format!("wat-{}-{}.pas", std::process::id(), n));
+    fs::write(&path, source).unwrap();
+    path
+}
+
+fn emit(source: &str, args: &[&str]) -> Module{
+    let path = temp_file(source);
+    let m = emit_file(&path, args);
+    fs::remove_file(&path).unwrap();
+    m
+}
+
+fn program(vars: &str, body: &str) -> String{
+    format!("PROGRAM Test;\nVAR\n{}\nBEGIN\n{}\nEND.\n", vars, body)
+}
+
+fn contains(data: &[u8], s: &str) -> bool{
+    data.windows(s.len()).any(|w| w == s.as_bytes())
+}
+
+#[test]
+fn module_has_host_imports_memory_and_main(){
+    let m = emit(&program("a : INTEGER; x : REAL; r : 1..10;", "a := 1; x := a / 2; r := a + 1"), &[]);
+    let imports: Vec<(&str, &str)> = m.imports.iter().map(|(a, b)| (a.as_str(), b.as_str())).collect();
+    assert_eq!(imports, [("host", "write"), ("host", "write_i64"), ("host", "write_f64")]);
+    assert_eq!(m.funcs["$write"].params, ["i32", "i32", "i32"]);
+    assert_eq!(m.funcs["$write_i64"].params, ["i32", "i64"]);
+    assert_eq!(m.funcs["$write_f64"].params, ["i32", "f64"]);
+    assert_eq!(m.memory_export.as_deref(), Some("memory"));
+
+    let main = &m.funcs["$main"];
+    assert_eq!(main.export.as_deref(), Some("main"));
+    assert!(main.params.is_empty() && main.results.is_empty());
+    let locals: Vec<(&str, &str)> = main.local_order.iter().map(|(n, t)| (n.as_str(), t.as_str())).collect();
+    assert_eq!(locals, [("$v_a", "i64"), ("$v_a_set", "i32"), ("$v_x", "f64"), ("$v_x_set", "i32"), ("$v_r", "i64"), ("$v_r_set", "i32")]);
+    let exported: Vec<&str> = m.funcs.values().filter_map(|f| f.export.as_deref()).collect();
+    assert_eq!(exported, ["main"]);
+}
+
+#[test]
+fn every_function_type_checks(){
+    let body = "a := 7 MOD 3 + -7 DIV 2 * 100 - b;\n\
+                x := -(a / 2) + 1.5 * a - 0.25;\n\
+                BEGIN r := a MOD 10 + 1; ; END;\n\
+                x := +x / 3";
+    let vars = "a, b : INTEGER; x : REAL; r : 1..10;";
+    for args in [&[][..], &["--no-overflow-checks"], &["--optimize"]].iter(){
+        emit(&program(vars, body), args);
+    }
+    emit(&program(vars, &format!("{{$Q-}}{{$R-}}\n{}", body)), &[]);
+}
+
+#[test]
+fn memory_holds_the_dump_and_the_error_messages(){
+    let source = program("Total : INTEGER; ratio : REAL; unused : INTEGER;", "Total := 10 DIV 0;\nratio := TOTAL / 4");
+    let m = emit(&source, &[]);
+    for s in ["Total: INTEGER(", "ratio: REAL(", "unused: <not initialized>\n", ")\n", ": runtime error: ",
+        "division by zero", "integer overflow", "variable `TOTAL` has not been initialized"].iter(){
+        assert!(contains(&m.data, s), "missing {:?} in memory", s);
+    }
+
+    //the division by zero is reported where the interpreter reports it
+    let path = temp_file(&source);
+    let m = emit_file(&path, &[]);
+    let out = Command::new(env!("CARGO_BIN_EXE_interpreter-ast")).arg("run").arg(&path).output().unwrap();
+    fs::remove_file(&path).unwrap();
+    let stderr = String::from_utf8(out.stderr).unwrap();
+    let location = stderr.split(": runtime error").next().unwrap();
+    assert!(location.ends_with(":5:17"), "{}", stderr);
+    assert!(contains(&m.data, location), "missing {:?} in memory", location);
+}